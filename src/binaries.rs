@@ -0,0 +1,144 @@
+use crate::{
+    PKGSTYLE, VERSIONSTYLE,
+    scope::Scope,
+    store::{self, InstalledPackage},
+};
+use anyhow::Result;
+use libsnow::metadata::Metadata;
+use owo_colors::{OwoColorize, Stream::Stdout};
+use std::{
+    collections::BTreeMap,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+/// Executables in the `bin` directories of `paths`
+pub fn binaries(paths: &[PathBuf]) -> Vec<String> {
+    let mut bins = paths
+        .iter()
+        .filter_map(|x| std::fs::read_dir(x.join("bin")).ok())
+        .flat_map(|x| x.flatten())
+        .filter(|x| {
+            x.path()
+                .metadata()
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+        .map(|x| x.file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    bins.sort();
+    bins.dedup();
+    bins
+}
+
+/// Resolve `command` the way the shell would, using `PATH`
+pub fn find_in_path(command: &str) -> Option<PathBuf> {
+    if command.contains('/') {
+        let path = PathBuf::from(command);
+        return path.exists().then_some(path);
+    }
+    std::env::var("PATH")
+        .ok()?
+        .split(':')
+        .filter(|x| !x.is_empty())
+        .map(|dir| Path::new(dir).join(command))
+        .find(|x| {
+            x.metadata()
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+}
+
+pub fn print(scope: Scope, installed: &[InstalledPackage]) {
+    println!(
+        "{}",
+        scope.title().if_supports_color(Stdout, |t| t.bright_cyan())
+    );
+    let mut installed = installed.to_vec();
+    installed.sort_by(|a, b| a.attr.cmp(&b.attr));
+    for pkg in installed {
+        match &pkg.version {
+            Some(v) => println!(
+                "{} ({})",
+                pkg.attr,
+                v.if_supports_color(Stdout, |t| t.style(*VERSIONSTYLE))
+            ),
+            None => println!("{}", pkg.attr),
+        }
+        for bin in binaries(&pkg.paths) {
+            println!("  {}", bin);
+        }
+    }
+}
+
+/// Package providing a command
+#[derive(Debug, Clone)]
+pub struct Provider {
+    pub scope: Scope,
+    pub attr: String,
+    /// Store path the command resolves to
+    pub path: PathBuf,
+}
+
+/// Commands provided by more than one installed package
+pub fn conflicts(md: &Metadata) -> Result<BTreeMap<String, Vec<Provider>>> {
+    let mut commands: BTreeMap<String, Vec<Provider>> = BTreeMap::new();
+    for scope in Scope::ALL.into_iter().filter(Scope::is_configured) {
+        for pkg in store::installed(scope, md)? {
+            for bin in binaries(&pkg.paths) {
+                let Some(path) = pkg
+                    .paths
+                    .iter()
+                    .map(|x| x.join("bin").join(&bin))
+                    .find(|x| x.exists())
+                    .and_then(|x| x.canonicalize().ok())
+                else {
+                    continue;
+                };
+                commands.entry(bin).or_default().push(Provider {
+                    scope,
+                    attr: pkg.attr.clone(),
+                    path,
+                });
+            }
+        }
+    }
+    commands.retain(|_, providers| {
+        providers
+            .iter()
+            .any(|x| providers.iter().any(|y| x.path != y.path))
+    });
+    Ok(commands)
+}
+
+pub async fn print_conflicts() -> Result<()> {
+    let md = Metadata::connect().await?;
+    let conflicts = conflicts(&md)?;
+    if conflicts.is_empty() {
+        println!("No conflicting commands found");
+        return Ok(());
+    }
+    for (command, providers) in conflicts {
+        let winner = find_in_path(&command).and_then(|x| x.canonicalize().ok());
+        println!(
+            "{}",
+            command.if_supports_color(Stdout, |t| t.style(*PKGSTYLE))
+        );
+        for provider in providers {
+            let wins = winner.as_ref() == Some(&provider.path);
+            println!(
+                "  {} {} ({}){}",
+                if wins { "*" } else { " " },
+                provider.attr,
+                provider.scope,
+                if wins {
+                    format!(
+                        " {}",
+                        "first in PATH".if_supports_color(Stdout, |t| t.bright_green())
+                    )
+                } else {
+                    String::new()
+                }
+            );
+        }
+    }
+    Ok(())
+}
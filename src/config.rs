@@ -0,0 +1,437 @@
+use crate::scope::Scope;
+use anyhow::{Context, Result, anyhow};
+use libsnow::config::configfile::{ConfigFile, get_config, get_config_path, set_config};
+use serde::Deserialize;
+use std::{collections::HashMap, path::PathBuf, process::Command};
+
+/// Settings used only by snow, stored in the `[snow]` table of the settings file. libsnow never
+/// reads them, the configuration files of the scopes are kept in the libsnow configuration.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Settings {
+    /// Scopes to keep a package in when removing duplicates, most preferred first
+    pub dedupe_prefer: Vec<Scope>,
+    /// NixOS configuration to act on instead of the one of this machine
+    pub host: Option<String>,
+    /// Home Manager configuration to act on instead of the one of the current user
+    pub home_config: Option<String>,
+    /// Package files of NixOS configurations, by name
+    pub hosts: HashMap<String, PathBuf>,
+    /// Package files of Home Manager configurations, by name
+    pub homes: HashMap<String, PathBuf>,
+    /// Files `--file` categories refer to, relative to the flake
+    pub files: HashMap<String, PathBuf>,
+}
+
+/// Path of the file holding settings used only by snow, `SNOW_CONFIG` overrides it.
+/// It does not change where libsnow reads its configuration from.
+pub fn config_path() -> PathBuf {
+    if let Ok(path) = std::env::var("SNOW_CONFIG")
+        && !path.is_empty()
+    {
+        return PathBuf::from(path);
+    }
+    let base = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(format!(
+            "{}/.config",
+            std::env::var("HOME").unwrap_or_default()
+        )),
+    };
+    base.join("snow").join("config.toml")
+}
+
+/// Directory for caches kept by snow
+pub fn cache_dir() -> PathBuf {
+    let base = match std::env::var("XDG_CACHE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(format!(
+            "{}/.cache",
+            std::env::var("HOME").unwrap_or_default()
+        )),
+    };
+    base.join("snow")
+}
+
+/// Read the raw settings table
+pub fn read() -> Result<toml::Table> {
+    let path = config_path();
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    toml::from_str(&content).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Write `table` as the settings file, creating its directory if needed
+pub fn write(table: &toml::Table) -> Result<()> {
+    let path = config_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    std::fs::write(&path, toml::to_string(table)?)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn libsnow_path(path: Option<String>) -> Option<PathBuf> {
+    path.filter(|x| !x.is_empty()).map(PathBuf::from)
+}
+
+/// NixOS configuration file edited by system installs, as set in the libsnow configuration
+pub fn system_config_file() -> Option<PathBuf> {
+    libsnow_path(get_config().ok()?.system_config_file)
+}
+
+/// Home Manager configuration file edited by home installs, as set in the libsnow configuration
+pub fn home_config_file() -> Option<PathBuf> {
+    libsnow_path(get_config().ok()?.home_config_file)
+}
+
+pub fn settings() -> Settings {
+    read()
+        .ok()
+        .and_then(|x| x.get("snow").cloned())
+        .and_then(|x| x.try_into().ok())
+        .unwrap_or_default()
+}
+
+/// Kind of value a configuration key holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Path to an existing file
+    File,
+    /// List of scopes
+    Scopes,
+    /// Plain string
+    String,
+    /// Path to a file that may not exist yet
+    Path,
+}
+
+/// Keys understood by snow, with their kind and description
+const KEYS: &[(&str, Kind, &str)] = &[
+    (
+        "system_config_file",
+        Kind::File,
+        "NixOS configuration file edited by system installs",
+    ),
+    (
+        "home_config_file",
+        Kind::File,
+        "Home Manager configuration file edited by home installs",
+    ),
+    (
+        "snow.dedupe_prefer",
+        Kind::Scopes,
+        "Scopes to keep a package in when removing duplicates, most preferred first",
+    ),
+    (
+        "snow.host",
+        Kind::String,
+        "NixOS configuration to act on instead of the one of this machine",
+    ),
+    (
+        "snow.home_config",
+        Kind::String,
+        "Home Manager configuration to act on instead of the one of the current user",
+    ),
+    (
+        "snow.hosts.*",
+        Kind::File,
+        "Package file of the NixOS configuration *",
+    ),
+    (
+        "snow.homes.*",
+        Kind::File,
+        "Package file of the Home Manager configuration *",
+    ),
+    (
+        "snow.files.*",
+        Kind::Path,
+        "File `--file *` adds packages to, relative to the flake",
+    ),
+];
+
+fn kind(key: &str) -> Result<Kind> {
+    KEYS.iter()
+        .find(|(k, _, _)| match k.strip_suffix('*') {
+            Some(prefix) => key.len() > prefix.len() && key.starts_with(prefix),
+            None => *k == key,
+        })
+        .map(|(_, kind, _)| *kind)
+        .ok_or_else(|| {
+            anyhow!(
+                "unknown key {}, valid keys are: {}",
+                key,
+                KEYS.iter()
+                    .map(|(k, _, _)| *k)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
+/// Check that `value` is valid for a key of `kind`
+fn validate_value(key: &str, kind: Kind, value: &toml::Value) -> Result<()> {
+    match kind {
+        Kind::File => {
+            let path = value
+                .as_str()
+                .ok_or_else(|| anyhow!("{} must be a string", key))?;
+            if !std::path::Path::new(path).is_file() {
+                return Err(anyhow!("{}: {} is not a file", key, path));
+            }
+        }
+        Kind::String | Kind::Path => {
+            if !value.is_str() {
+                return Err(anyhow!("{} must be a string", key));
+            }
+        }
+        Kind::Scopes => {
+            let scopes: Vec<Scope> = value
+                .clone()
+                .try_into()
+                .map_err(|_| anyhow!("{} must be a list of profile, system or home", key))?;
+            if scopes
+                .iter()
+                .enumerate()
+                .any(|(i, x)| scopes[..i].contains(x))
+            {
+                return Err(anyhow!("{} lists a scope more than once", key));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Look up a dotted `key` in `table`
+fn lookup<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let (first, rest) = key.split_once('.').unwrap_or((key, ""));
+    let value = table.get(first)?;
+    if rest.is_empty() {
+        Some(value)
+    } else {
+        lookup(value.as_table()?, rest)
+    }
+}
+
+/// Dotted keys of all values in `table`, tables that only group other keys are flattened
+fn keys(table: &toml::Table, prefix: &str, keys: &mut Vec<String>) {
+    for (key, value) in table {
+        let key = format!("{}{}", prefix, key);
+        match value.as_table() {
+            Some(inner)
+                if KEYS
+                    .iter()
+                    .any(|(k, _, _)| k.starts_with(&format!("{}.", key))) =>
+            {
+                self::keys(inner, &format!("{}.", key), keys)
+            }
+            _ => keys.push(key),
+        }
+    }
+}
+
+/// Whether `key` is kept in the libsnow configuration rather than the settings file
+fn is_libsnow_key(key: &str) -> bool {
+    !key.starts_with("snow.")
+}
+
+/// Value of the libsnow configuration key `key`
+fn libsnow_field<'a>(configfile: &'a mut ConfigFile, key: &str) -> &'a mut Option<String> {
+    match key {
+        "home_config_file" => &mut configfile.home_config_file,
+        _ => &mut configfile.system_config_file,
+    }
+}
+
+/// Problems with the keys and values of `table`, the contents of the settings file
+pub fn validate(table: &toml::Table) -> Vec<String> {
+    let mut problems = vec![];
+    let mut all = vec![];
+    keys(table, "", &mut all);
+    for key in all {
+        let Ok(kind) = kind(&key) else {
+            problems.push(format!("unknown key {}", key));
+            continue;
+        };
+        if is_libsnow_key(&key) {
+            problems.push(format!(
+                "{} is ignored here, set it in the libsnow configuration with `snow config set {} <path>`",
+                key, key
+            ));
+            continue;
+        }
+        if let Some(value) = lookup(table, &key)
+            && let Err(e) = validate_value(&key, kind, value)
+        {
+            problems.push(e.to_string());
+        }
+    }
+    problems
+}
+
+/// Problems with the values of the libsnow configuration
+fn validate_libsnow(configfile: &ConfigFile) -> Vec<String> {
+    let mut configfile = configfile.clone();
+    ["system_config_file", "home_config_file"]
+        .into_iter()
+        .filter_map(|key| {
+            let value = libsnow_field(&mut configfile, key).clone()?;
+            validate_value(key, Kind::File, &value.into()).err()
+        })
+        .map(|e| e.to_string())
+        .collect()
+}
+
+/// Print the value of `key`
+pub fn get(key: &str) -> Result<()> {
+    kind(key)?;
+    if is_libsnow_key(key) {
+        let mut configfile = get_config()?;
+        let value = libsnow_field(&mut configfile, key)
+            .clone()
+            .ok_or_else(|| anyhow!("{} is not set", key))?;
+        println!("{}", value);
+        return Ok(());
+    }
+    let table = read()?;
+    let value = lookup(&table, key).ok_or_else(|| anyhow!("{} is not set", key))?;
+    match value {
+        toml::Value::String(x) => println!("{}", x),
+        toml::Value::Array(x) => println!(
+            "{}",
+            x.iter()
+                .map(|x| x.as_str().map(str::to_string).unwrap_or(x.to_string()))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        x => println!("{}", x),
+    }
+    Ok(())
+}
+
+/// Set `key` to `value`, lists are given comma separated and an empty value removes the key.
+/// `system_config_file` and `home_config_file` are written to the libsnow configuration.
+pub fn set(key: &str, value: &str) -> Result<()> {
+    let kind = kind(key)?;
+    let value: Option<toml::Value> = if value.is_empty() {
+        None
+    } else {
+        let value = match kind {
+            Kind::File => std::path::Path::new(value)
+                .canonicalize()
+                .map(|x| x.to_string_lossy().to_string())
+                .unwrap_or(value.to_string())
+                .into(),
+            Kind::Scopes => value
+                .split(',')
+                .map(|x| toml::Value::from(x.trim().to_lowercase()))
+                .collect::<Vec<_>>()
+                .into(),
+            Kind::String | Kind::Path => value.into(),
+        };
+        validate_value(key, kind, &value)?;
+        Some(value)
+    };
+
+    if is_libsnow_key(key) {
+        let mut configfile = get_config().unwrap_or_default();
+        *libsnow_field(&mut configfile, key) = value.and_then(|x| x.as_str().map(str::to_string));
+        return set_config(&configfile);
+    }
+
+    let mut table = if config_path().exists() {
+        read()?
+    } else {
+        toml::Table::new()
+    };
+    let (path, name) = key.rsplit_once('.').unwrap_or(("", key));
+    let mut parent = &mut table;
+    for segment in path.split('.').filter(|x| !x.is_empty()) {
+        parent = parent
+            .entry(segment)
+            .or_insert_with(|| toml::Table::new().into())
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("{} is not a table", segment))?;
+    }
+    match value {
+        Some(value) => parent.insert(name.to_string(), value),
+        None => parent.remove(name),
+    };
+    write(&table)
+}
+
+/// Print the libsnow configuration and the settings file, and any problems with them
+pub fn show() -> Result<()> {
+    let path = get_config_path()?;
+    if !path.exists() {
+        return Err(anyhow!(
+            "{} does not exist, run `snow init` to create it",
+            path.display()
+        ));
+    }
+    let configfile = get_config()?;
+    println!("# {}", path.display());
+    for (key, value) in [
+        ("system_config_file", &configfile.system_config_file),
+        ("home_config_file", &configfile.home_config_file),
+    ] {
+        if let Some(value) = value {
+            println!("{} = {}", key, toml::Value::from(value.as_str()));
+        }
+    }
+    let mut problems = validate_libsnow(&configfile);
+
+    let settings = config_path();
+    if settings.exists() {
+        let table = read()?;
+        println!("\n# {}", settings.display());
+        print!("{}", toml::to_string(&table)?);
+        problems.extend(validate(&table));
+    }
+    for problem in problems {
+        eprintln!("warning: {}", problem);
+    }
+    Ok(())
+}
+
+/// Open the libsnow configuration, or with `settings` the settings file, in `$VISUAL` or
+/// `$EDITOR` and check it afterwards
+pub fn edit(settings: bool) -> Result<()> {
+    let path = if settings {
+        config_path()
+    } else {
+        get_config_path()?
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let editor = ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|x| std::env::var(x).ok())
+        .find(|x| !x.is_empty())
+        .unwrap_or("nano".to_string());
+    // The editor may be given with arguments, e.g. `code --wait`
+    let mut args = editor.split_whitespace();
+    let status = Command::new(args.next().unwrap_or("nano"))
+        .args(args)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("failed to run {}", editor))?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", editor, status));
+    }
+    if !path.exists() {
+        return Ok(());
+    }
+    let problems = if settings {
+        validate(&read()?)
+    } else {
+        validate_libsnow(&get_config()?)
+    };
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(problems.join("\n")))
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{
+    PKGSTYLE, VERSIONSTYLE, WARNINGSTYLE,
+    scope::{self, Scope, attr_name},
+};
+use anyhow::Result;
+use libsnow::{Package, metadata::Metadata};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use std::collections::{BTreeMap, HashMap};
+
+/// A package installed in a single scope
+#[derive(Debug, Clone)]
+pub struct Install {
+    pub scope: Scope,
+    /// Name the scope knows the package by, used when removing it
+    pub name: String,
+    pub version: Option<String>,
+}
+
+/// Attribute installed in more than one scope
+#[derive(Debug, Clone)]
+pub struct Duplicate {
+    pub attr: String,
+    pub installs: Vec<Install>,
+}
+
+/// Find attributes installed in more than one of the configured scopes
+pub fn find(md: &Metadata) -> Vec<Duplicate> {
+    let lists = Scope::ALL
+        .into_iter()
+        .filter(Scope::is_configured)
+        .map(|s| (s, scope::list(s, md).unwrap_or_default()))
+        .collect::<Vec<_>>();
+    find_in(&lists)
+}
+
+/// Find attributes that appear in more than one of the package lists of `lists`
+pub fn find_in(lists: &[(Scope, Vec<Package>)]) -> Vec<Duplicate> {
+    let mut attrs: BTreeMap<String, Vec<Install>> = BTreeMap::new();
+    for (s, lst) in lists {
+        for pkg in lst {
+            let name = pkg.attr.to_string();
+            attrs.entry(attr_name(&name)).or_default().push(Install {
+                scope: *s,
+                name,
+                version: pkg.version.clone(),
+            });
+        }
+    }
+    attrs
+        .into_iter()
+        .filter(|(_, installs)| {
+            installs
+                .iter()
+                .any(|x| installs.iter().any(|y| x.scope != y.scope))
+        })
+        .map(|(attr, installs)| Duplicate { attr, installs })
+        .collect()
+}
+
+pub fn print(duplicates: &[Duplicate]) {
+    for dup in duplicates {
+        let installs = dup
+            .installs
+            .iter()
+            .map(|x| match &x.version {
+                Some(v) => format!(
+                    "{} ({})",
+                    x.scope,
+                    v.if_supports_color(Stdout, |t| t.style(*VERSIONSTYLE))
+                ),
+                None => x.scope.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "{}: {}",
+            dup.attr.if_supports_color(Stdout, |t| t.style(*PKGSTYLE)),
+            installs
+        );
+    }
+}
+
+pub fn warn(duplicates: &[Duplicate]) {
+    for dup in duplicates {
+        let scopes = dup
+            .installs
+            .iter()
+            .map(|x| x.scope.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        eprintln!(
+            "{} {} is installed in multiple scopes ({})",
+            "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+            dup.attr,
+            scopes
+        );
+    }
+}
+
+/// Order scopes are kept in: `prefer`, or `dedupe_prefer` from the settings when empty,
+/// followed by any remaining scopes in declarative-first order
+pub fn preference(prefer: &[Scope]) -> Vec<Scope> {
+    let mut order = if prefer.is_empty() {
+        crate::config::settings().dedupe_prefer
+    } else {
+        prefer.to_vec()
+    };
+    for s in [Scope::System, Scope::Home, Scope::Profile] {
+        if !order.contains(&s) {
+            order.push(s);
+        }
+    }
+    order
+}
+
+/// Keep each duplicate in its most preferred scope and remove it from the others
+pub async fn dedupe(prefer: &[Scope], dry_run: bool) -> Result<()> {
+    let order = preference(prefer);
+    let md = Metadata::connect().await?;
+    let duplicates = find(&md);
+    if duplicates.is_empty() {
+        println!("No duplicate packages found");
+        return Ok(());
+    }
+
+    let mut removals: HashMap<Scope, Vec<String>> = HashMap::new();
+    for dup in &duplicates {
+        let keep = order
+            .iter()
+            .find(|s| dup.installs.iter().any(|x| x.scope == **s))
+            .copied()
+            .unwrap_or(dup.installs[0].scope);
+        println!(
+            "{}: keeping {}",
+            dup.attr.if_supports_color(Stdout, |t| t.style(*PKGSTYLE)),
+            keep
+        );
+        for install in dup.installs.iter().filter(|x| x.scope != keep) {
+            println!("  removing from {}", install.scope);
+            removals
+                .entry(install.scope)
+                .or_default()
+                .push(install.name.clone());
+        }
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+    for (s, names) in removals {
+        let p: Vec<&str> = names.iter().map(|x| &**x).collect();
+        scope::remove(s, &p, &md).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,343 @@
+use crate::{
+    ERRORSTYLE, WARNINGSTYLE, binaries::find_in_path, config, home_manager_installed,
+    is_home_configured, is_profile_configured, is_system_configured,
+};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Result of a single diagnostic
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub status: Status,
+    pub message: String,
+    /// What to do about a failed check
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(message: impl Into<String>) -> Self {
+        Check {
+            status: Status::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    fn warning(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check {
+            status: Status::Warning,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn error(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check {
+            status: Status::Error,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+fn command_output(cmd: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(cmd).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn check_nix() -> Vec<Check> {
+    let Some(version) = command_output("nix", &["--version"]) else {
+        return vec![Check::error(
+            "nix is not installed or not in PATH",
+            "install Nix from https://nixos.org/download",
+        )];
+    };
+    let mut checks = vec![Check::ok(version)];
+
+    let features = command_output("nix", &["config", "show", "experimental-features"])
+        .or_else(|| {
+            command_output("nix", &["show-config"]).and_then(|x| {
+                x.lines()
+                    .find_map(|l| l.strip_prefix("experimental-features = "))
+                    .map(str::to_string)
+            })
+        })
+        .unwrap_or_default();
+    let missing = ["nix-command", "flakes"]
+        .into_iter()
+        .filter(|f| !features.split_whitespace().any(|x| x == *f))
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        checks.push(Check::ok("nix-command and flakes are enabled"));
+    } else {
+        checks.push(Check::error(
+            format!("experimental features not enabled: {}", missing.join(", ")),
+            "add `experimental-features = nix-command flakes` to ~/.config/nix/nix.conf, \
+             or set `nix.settings.experimental-features` on NixOS",
+        ));
+    }
+    checks
+}
+
+fn check_config() -> Vec<Check> {
+    let mut checks = vec![];
+    let path = match libsnow::config::configfile::get_config_path() {
+        Ok(path) => path,
+        Err(e) => {
+            return vec![Check::error(
+                format!("failed to locate the configuration file: {:#}", e),
+                "run `snow init` to create one",
+            )];
+        }
+    };
+    if !path.exists() {
+        checks.push(Check::warning(
+            format!("no configuration file at {}", path.display()),
+            "run `snow init` to create one, only the profile scope works without it",
+        ));
+    } else if let Err(e) = libsnow::config::configfile::get_config() {
+        checks.push(Check::error(
+            format!("{:#}", e),
+            format!("fix the syntax of {} or run `snow init`", path.display()),
+        ));
+    } else {
+        checks.push(Check::ok(format!("configuration file {}", path.display())));
+    }
+
+    // The settings file only holds settings of snow itself and is optional
+    let settings = config::config_path();
+    if settings.exists() {
+        match config::read() {
+            Ok(table) => {
+                for problem in config::validate(&table) {
+                    checks.push(Check::warning(
+                        format!("{}: {}", settings.display(), problem),
+                        "fix it with `snow config edit`",
+                    ));
+                }
+            }
+            Err(e) => checks.push(Check::error(
+                format!("{:#}", e),
+                format!("fix the syntax of {}", settings.display()),
+            )),
+        }
+    }
+
+    for (key, value) in [
+        ("system_config_file", config::system_config_file()),
+        ("home_config_file", config::home_config_file()),
+    ] {
+        let Some(file) = value else {
+            continue;
+        };
+        let file = file.as_path();
+        let value = file.display();
+        if !file.exists() {
+            checks.push(Check::error(
+                format!("{} points to {}, which does not exist", key, value),
+                format!("run `snow config set {} <path>` with the correct file", key),
+            ));
+            continue;
+        }
+        let writable = std::fs::OpenOptions::new().append(true).open(file).is_ok();
+        if writable {
+            checks.push(Check::ok(format!("{} {} is writable", key, value)));
+        } else if let Some(tool) = privilege_tools().first() {
+            checks.push(Check::ok(format!(
+                "{} {} is writable through {}",
+                key, value, tool
+            )));
+        } else {
+            checks.push(Check::error(
+                format!(
+                    "{} {} is not writable and none of sudo, doas or pkexec is available",
+                    key, value
+                ),
+                format!("make {} writable by your user or install sudo", value),
+            ));
+        }
+        if crate::flake::flake_dir(file).is_none() {
+            checks.push(Check::warning(
+                format!("{} is not part of a flake", value),
+                "snow only works with flake based configurations",
+            ));
+        }
+    }
+    checks
+}
+
+fn check_scopes() -> Vec<Check> {
+    let mut checks = vec![];
+    let home = std::env::var("HOME").unwrap_or_default();
+
+    if crate::legacy::detected() {
+        checks.push(Check::warning(
+            format!("profile scope: {}/.nix-profile is a nix-env profile", home),
+            "run `snow profile convert` to switch it to `nix profile`",
+        ));
+    } else if is_profile_configured() {
+        checks.push(Check::ok(format!(
+            "profile scope: {}/.nix-profile/manifest.json found",
+            home
+        )));
+    } else {
+        checks.push(Check::warning(
+            format!(
+                "profile scope not detected: {}/.nix-profile/manifest.json does not exist",
+                home
+            ),
+            "install a package with `snow install <package>` to create a profile",
+        ));
+    }
+
+    if is_system_configured() {
+        checks.push(Check::ok(
+            "system scope: NixOS configuration is set in the configuration file",
+        ));
+    } else if Path::new("/etc/NIXOS").exists() {
+        checks.push(Check::warning(
+            "system scope not detected: NixOS configuration is not set in the configuration file",
+            "run `snow init` or `snow config set system_config_file <path>`",
+        ));
+    }
+
+    let standalone =
+        Path::new(&format!("{}/.local/state/nix/profiles/home-manager", home)).is_symlink();
+    if crate::homemodule::detected() {
+        checks.push(Check::ok(
+            "home scope: Home Manager is used as a NixOS module, changes rebuild the system",
+        ));
+        return checks;
+    }
+    match (home_manager_installed(), is_home_configured()) {
+        (true, true) => checks.push(Check::ok(if standalone {
+            "home scope: standalone Home Manager found and configured"
+        } else {
+            "home scope: Home Manager configuration is set in the configuration file"
+        })),
+        (true, false) => checks.push(Check::warning(
+            "home scope not detected: Home Manager is installed but its configuration is not set",
+            "run `snow init` or `snow config set home_config_file <path>`",
+        )),
+        (false, _) => checks.push(Check::warning(
+            "home scope not detected: Home Manager is not installed",
+            "install Home Manager to use `--home`",
+        )),
+    }
+    checks
+}
+
+/// Days after which the metadata database is considered outdated
+const METADATA_MAX_AGE: u64 = 30;
+
+/// Newest metadata database in the cache directories libsnow and snow use
+fn metadata_database() -> Option<(PathBuf, SystemTime)> {
+    let libsnow = config::cache_dir().with_file_name("libsnow");
+    [libsnow, config::cache_dir()]
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|x| x == "db"))
+        .filter_map(|path| {
+            let modified = path.metadata().and_then(|x| x.modified()).ok()?;
+            Some((path, modified))
+        })
+        .max_by_key(|(_, modified)| *modified)
+}
+
+fn check_metadata() -> Vec<Check> {
+    let Some((path, modified)) = metadata_database() else {
+        return vec![Check::warning(
+            "metadata database not found",
+            "run `snow search <package>` with a network connection to download it",
+        )];
+    };
+    let days = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default()
+        .as_secs()
+        / (24 * 60 * 60);
+    if days > METADATA_MAX_AGE {
+        vec![Check::warning(
+            format!("metadata database {} is {} days old", path.display(), days),
+            format!("delete {} to download a fresh one", path.display()),
+        )]
+    } else {
+        vec![Check::ok(format!(
+            "metadata database {} is {} days old",
+            path.display(),
+            days
+        ))]
+    }
+}
+
+/// The tools libsnow can authenticate system changes with that are installed
+fn privilege_tools() -> Vec<&'static str> {
+    ["sudo", "doas", "pkexec"]
+        .into_iter()
+        .filter(|x| find_in_path(x).is_some())
+        .collect()
+}
+
+fn check_privileges() -> Vec<Check> {
+    let tools = privilege_tools();
+    if tools.is_empty() {
+        vec![Check::error(
+            "none of sudo, doas or pkexec is available",
+            "install sudo, or enable `security.sudo` on NixOS, to change system packages",
+        )]
+    } else {
+        vec![Check::ok(format!(
+            "privilege tools available: {}",
+            tools.join(", ")
+        ))]
+    }
+}
+
+pub async fn diagnose() -> Vec<Check> {
+    let mut checks = check_nix();
+    checks.extend(check_config());
+    checks.extend(check_scopes());
+    checks.extend(check_metadata());
+    checks.extend(check_privileges());
+    checks
+}
+
+/// Print the result of all checks, returns false if any of them failed
+pub async fn doctor() -> bool {
+    let checks = diagnose().await;
+    for check in &checks {
+        let label = match check.status {
+            Status::Ok => "ok:"
+                .if_supports_color(Stdout, |t| t.bright_green())
+                .to_string(),
+            Status::Warning => "warning:"
+                .if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE))
+                .to_string(),
+            Status::Error => "error:"
+                .if_supports_color(Stdout, |t| t.style(*ERRORSTYLE))
+                .to_string(),
+        };
+        println!("{} {}", label, check.message);
+        if let Some(fix) = &check.fix {
+            println!("  fix: {}", fix);
+        }
+    }
+    !checks.iter().any(|x| x.status == Status::Error)
+}
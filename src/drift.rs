@@ -0,0 +1,113 @@
+use crate::{
+    PKGSTYLE, flake, host,
+    scope::{self, Scope},
+    store,
+};
+use anyhow::{Result, anyhow};
+use libsnow::metadata::Metadata;
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::Deserialize;
+
+/// Differences between the declared and the built packages of a scope
+#[derive(Debug, Clone)]
+pub struct Drift {
+    pub scope: Scope,
+    /// Declared attributes whose package is not in the current generation, with the expected name
+    pub not_built: Vec<(String, String)>,
+    /// Packages in the current generation no longer declared by the configuration
+    pub not_declared: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Evaluated {
+    declared: Vec<Option<String>>,
+    all: Vec<String>,
+}
+
+/// Compare the packages declared for `scope` with its current generation
+pub fn drift(scope: Scope, md: &Metadata) -> Result<Drift> {
+    let home = match scope {
+        Scope::System => false,
+        Scope::Home => true,
+        Scope::Profile => return Err(anyhow!("the profile scope has no declarations")),
+    };
+    host::require_local(home)?;
+    let root = store::generation_root(scope)
+        .ok_or_else(|| anyhow!("no current {} generation found", scope))?;
+    let attrs = scope::list(scope, md)?
+        .into_iter()
+        .map(|x| x.attr.to_string())
+        .collect::<Vec<_>>();
+
+    let option = if home {
+        "home.packages"
+    } else {
+        "environment.systemPackages"
+    };
+    let apply = format!(
+        "c: {{ declared = {}; all = map (p: p.name or \"\") c.config.{}; }}",
+        flake::attr_names_expr(&attrs),
+        option
+    );
+    let evaluated: Evaluated = serde_json::from_str(&flake::eval_configuration(home, &apply)?)?;
+
+    let built = store::references(&root)?
+        .iter()
+        .map(|x| store::store_name(x))
+        .collect::<Vec<_>>();
+
+    let not_built = attrs
+        .into_iter()
+        .zip(evaluated.declared)
+        .filter_map(|(attr, name)| name.map(|n| (attr, n)))
+        .filter(|(_, name)| !built.contains(name))
+        .collect();
+    let mut not_declared = built
+        .into_iter()
+        .filter(|x| !evaluated.all.contains(x))
+        .collect::<Vec<_>>();
+    not_declared.sort();
+    not_declared.dedup();
+
+    Ok(Drift {
+        scope,
+        not_built,
+        not_declared,
+    })
+}
+
+pub fn print(drift: &Drift) {
+    let title = match drift.scope {
+        Scope::System => "System",
+        Scope::Home => "Home Manager",
+        Scope::Profile => "Profile",
+    };
+    if drift.not_built.is_empty() && drift.not_declared.is_empty() {
+        println!(
+            "{}",
+            format!("{} configuration matches the current generation", title)
+                .if_supports_color(Stdout, |t| t.bright_cyan())
+        );
+        return;
+    }
+    println!(
+        "{}",
+        format!("{} configuration:", title).if_supports_color(Stdout, |t| t.bright_cyan())
+    );
+    if !drift.not_built.is_empty() {
+        println!("  Declared but not built (rebuild to apply):");
+        for (attr, name) in &drift.not_built {
+            println!(
+                "    {} ({})",
+                attr.if_supports_color(Stdout, |t| t.style(*PKGSTYLE)),
+                name
+            );
+        }
+    }
+    if !drift.not_declared.is_empty() {
+        println!("  Built but no longer declared:");
+        for name in &drift.not_declared {
+            println!("    {}", name);
+        }
+    }
+}
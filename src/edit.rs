@@ -0,0 +1,161 @@
+use crate::flake::{flake_dir, nix_files};
+use anyhow::{Context, Result, anyhow};
+use std::{
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+pub fn read(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Write `content` to `path`, going through sudo when the file is not writable by the current user
+pub fn write(path: &Path, content: &str) -> Result<()> {
+    match std::fs::write(path, content) {
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {}
+        x => return x.with_context(|| format!("failed to write {}", path.display())),
+    }
+
+    let mut child = Command::new("sudo")
+        .arg("tee")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .context("failed to run sudo")?;
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("failed to open stdin of sudo"))?
+        .write_all(content.as_bytes())?;
+    if !child.wait()?.success() {
+        return Err(anyhow!("failed to write {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Set `option` to the Nix expression `value` in the file at `path`
+pub fn set_option(path: &Path, option: &str, value: &str) -> Result<()> {
+    let content = read(path)?;
+    let new = nix_editor::write::write(&content, option, value)
+        .map_err(|e| anyhow!("failed to set {} in {}: {}", option, path.display(), e))?;
+    write(path, &new)
+}
+
+/// Attributes in the list `option` of the file at `path`
+pub fn list_packages(path: &Path, option: &str) -> Result<Vec<String>> {
+    let content = read(path)?;
+    let values = nix_editor::read::getarrvals(&content, option)
+        .map_err(|e| anyhow!("failed to read {} in {}: {}", option, path.display(), e))?;
+    Ok(values
+        .into_iter()
+        .map(|x| x.strip_prefix("pkgs.").unwrap_or(&x).to_string())
+        .collect())
+}
+
+/// Add `packages` to the list `option` in the file at `path`, skipping ones already in it.
+/// The list is created if the file does not set `option` yet.
+pub fn add_packages(path: &Path, option: &str, packages: &[&str]) -> Result<()> {
+    let mut content = read(path)?;
+    if nix_editor::read::readvalue(&content, option).is_err() {
+        content = nix_editor::write::write(&content, option, "[ ]")
+            .map_err(|e| anyhow!("failed to add {} to {}: {}", option, path.display(), e))?;
+    }
+    let current = nix_editor::read::getarrvals(&content, option).unwrap_or_default();
+    let new = packages
+        .iter()
+        .filter(|x| {
+            !current
+                .iter()
+                .any(|y| y == *x || y.strip_prefix("pkgs.") == Some(x))
+        })
+        .map(|x| format!("pkgs.{}", x))
+        .collect::<Vec<_>>();
+    if new.is_empty() {
+        return Ok(());
+    }
+    let content = nix_editor::write::addtoarr(&content, option, new)
+        .map_err(|e| anyhow!("failed to add to {} in {}: {}", option, path.display(), e))?;
+    write(path, &content)
+}
+
+/// Remove `packages` from the list `option` in the file at `path`
+pub fn remove_packages(path: &Path, option: &str, packages: &[&str]) -> Result<()> {
+    let content = read(path)?;
+    let current = nix_editor::read::getarrvals(&content, option)
+        .map_err(|e| anyhow!("failed to read {} in {}: {}", option, path.display(), e))?;
+    // Remove the entries as they are written, with or without the `pkgs.` prefix
+    let items = current
+        .into_iter()
+        .filter(|x| packages.contains(&x.strip_prefix("pkgs.").unwrap_or(x)))
+        .collect::<Vec<_>>();
+    if let Some(missing) = packages.iter().find(|x| {
+        !items
+            .iter()
+            .any(|y| y.strip_prefix("pkgs.").unwrap_or(y) == **x)
+    }) {
+        return Err(anyhow!(
+            "{} is not in {} in {}",
+            missing,
+            option,
+            path.display()
+        ));
+    }
+    let content = nix_editor::write::rmarr(&content, option, items).map_err(|e| {
+        anyhow!(
+            "failed to remove from {} in {}: {}",
+            option,
+            path.display(),
+            e
+        )
+    })?;
+    write(path, &content)
+}
+
+/// `<prefix>.<name>`, quoting names that are not plain identifiers
+pub fn attr(prefix: &str, name: &str) -> String {
+    if name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && !name.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+    {
+        format!("{}.{}", prefix, name)
+    } else {
+        format!("{}.\"{}\"", prefix, name)
+    }
+}
+
+/// Whether `content` mentions the attribute `<prefix>.<name>` itself. The name has to be
+/// followed by `.`, whitespace, `=` or a closing quote, so `users.users.alice` does not
+/// declare the user `al`.
+pub fn declares(content: &str, prefix: &str, name: &str) -> bool {
+    [
+        format!("{}.{}", prefix, name),
+        format!("{}.\"{}\"", prefix, name),
+    ]
+    .iter()
+    .any(|attr| {
+        content.match_indices(attr.as_str()).any(|(i, _)| {
+            content[i + attr.len()..]
+                .chars()
+                .next()
+                .is_none_or(|c| c == '.' || c == '=' || c == '"' || c.is_whitespace())
+        })
+    })
+}
+
+/// File declaring `<prefix>.<name>`: `base` if it does, otherwise the first file of the flake
+/// `base` is part of
+pub fn declaring_file(base: &Path, prefix: &str, name: &str) -> Option<PathBuf> {
+    let mentions =
+        |file: &PathBuf| std::fs::read_to_string(file).is_ok_and(|x| declares(&x, prefix, name));
+    let base = base.to_path_buf();
+    if mentions(&base) {
+        return Some(base);
+    }
+    let mut candidates = vec![];
+    nix_files(&flake_dir(&base)?, &mut candidates);
+    candidates.sort();
+    candidates.into_iter().find(mentions)
+}
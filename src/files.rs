@@ -0,0 +1,127 @@
+use crate::{
+    WARNINGSTYLE, config, edit,
+    flake::{flake_dir, nix_files},
+    host,
+    scope::Scope,
+    search,
+};
+use anyhow::{Result, anyhow};
+use libsnow::{metadata::Metadata, nixos::AuthMethod};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use std::path::{Path, PathBuf};
+
+/// Option holding the package list of `scope`
+fn option(scope: Scope) -> Result<&'static str> {
+    match scope {
+        Scope::System => Ok("environment.systemPackages"),
+        Scope::Home => Ok("home.packages"),
+        Scope::Profile => Err(anyhow!("--file only works with --system or --home")),
+    }
+}
+
+fn flake(scope: Scope) -> Result<PathBuf> {
+    let file = match scope {
+        Scope::Home => config::home_config_file().or_else(config::system_config_file),
+        _ => config::system_config_file().or_else(config::home_config_file),
+    }
+    .ok_or_else(|| anyhow!("configuration file is not set, run `snow init`"))?;
+    flake_dir(&file).ok_or_else(|| anyhow!("no flake.nix found above {}", file.display()))
+}
+
+/// Resolve `file`, a category from `[snow.files]` or a path, relative paths are taken from the
+/// root of the flake
+pub fn resolve(file: &str, scope: Scope) -> Result<PathBuf> {
+    let path = config::settings()
+        .files
+        .get(file)
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from(file));
+    if path.is_absolute() {
+        return Ok(path);
+    }
+    // A path relative to the working directory wins if it exists
+    if path.exists() {
+        return Ok(path.canonicalize()?);
+    }
+    Ok(flake(scope)?.join(path))
+}
+
+/// Warn when no other file of the flake mentions `path`, as it then is not imported anywhere
+pub fn warn_unimported(path: &Path, scope: Scope) {
+    let Ok(flake) = flake(scope) else {
+        return;
+    };
+    let Some(name) = path.file_name().map(|x| x.to_string_lossy().to_string()) else {
+        return;
+    };
+    // Modules named default.nix are imported through their directory
+    let name = match name.as_str() {
+        "default.nix" => path
+            .parent()
+            .and_then(|x| x.file_name())
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or(name),
+        _ => name,
+    };
+    let mut files = vec![];
+    nix_files(&flake, &mut files);
+    let imported = files
+        .iter()
+        .filter(|x| x.as_path() != path)
+        .any(|x| std::fs::read_to_string(x).is_ok_and(|x| x.contains(&name)));
+    if !imported {
+        println!(
+            "{} {} is not imported by any file in {}, add it to `imports`",
+            "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+            path.display(),
+            flake.display()
+        );
+    }
+}
+
+/// Rebuild the configuration of `scope` after its files were edited
+pub async fn rebuild(scope: Scope) -> Result<()> {
+    let home = scope == Scope::Home;
+    if let Some(name) = host::other(home) {
+        return host::rebuild(&name, home);
+    }
+    if home && !crate::homemodule::detected() {
+        libsnow::homemanager::rebuild::rebuild(AuthMethod::Sudo).await
+    } else {
+        libsnow::nixos::rebuild::rebuild(AuthMethod::Sudo).await
+    }
+}
+
+/// Create `path` as a module setting `option` to an empty list, unless it exists already
+pub fn create(path: &Path, option: &str) -> Result<()> {
+    if path.exists() {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    edit::write(
+        path,
+        &format!("{{ pkgs, ... }}:\n{{\n  {} = [ ];\n}}\n", option),
+    )?;
+    println!("Created {}", path.display());
+    Ok(())
+}
+
+/// Add `packages` to the package list of `file`, creating the file or list if needed, and rebuild
+pub async fn install(file: &str, scope: Scope, packages: &[&str], md: &Metadata) -> Result<()> {
+    let option = option(scope)?;
+    search::validate(md, packages)?;
+    let path = resolve(file, scope)?;
+    create(&path, option)?;
+    edit::add_packages(&path, option, packages)?;
+    warn_unimported(&path, scope);
+    rebuild(scope).await
+}
+
+/// Remove `packages` from the package list of `file` and rebuild
+pub async fn remove(file: &str, scope: Scope, packages: &[&str]) -> Result<()> {
+    let path = resolve(file, scope)?;
+    edit::remove_packages(&path, option(scope)?, packages)?;
+    rebuild(scope).await
+}
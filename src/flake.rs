@@ -0,0 +1,135 @@
+use anyhow::{Context, Result, anyhow};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Find the directory of the flake containing `file`
+pub fn flake_dir(file: &Path) -> Option<PathBuf> {
+    let file = file.canonicalize().ok()?;
+    file.ancestors()
+        .skip(1)
+        .find(|dir| dir.join("flake.nix").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Nix files below `dir`, skipping hidden directories and build results
+pub fn nix_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || name.starts_with("result") {
+            continue;
+        }
+        if path.is_dir() {
+            nix_files(&path, files);
+        } else if name.ends_with(".nix") {
+            files.push(path);
+        }
+    }
+}
+
+pub fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .map(|x| x.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// `value` as a Nix string literal, escaping quotes, backslashes and interpolations
+pub fn nix_string(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace("${", "\\${")
+    )
+}
+
+/// Evaluate `installable` with `nix eval --json`, optionally applying `apply` to the result
+pub fn eval_json(installable: &str, apply: Option<&str>) -> Result<String> {
+    let mut cmd = Command::new("nix");
+    cmd.args(["eval", "--json", installable]);
+    if let Some(apply) = apply {
+        cmd.args(["--apply", apply]);
+    }
+    let output = cmd.output().context("failed to run nix eval")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "failed to evaluate {}: {}",
+            installable,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Flake installable of the current NixOS or Home Manager configuration
+pub fn configuration(home: bool) -> Result<String> {
+    let file = if home {
+        crate::config::home_config_file()
+    } else {
+        crate::config::system_config_file()
+    }
+    .ok_or_else(|| anyhow!("configuration file is not set"))?;
+    let flake =
+        flake_dir(&file).ok_or_else(|| anyhow!("no flake.nix found above {}", file.display()))?;
+    let flake = flake.to_string_lossy();
+
+    if !home {
+        return Ok(format!(
+            "{}#nixosConfigurations.\"{}\"",
+            flake,
+            crate::host::host()
+        ));
+    }
+    if let Some(name) = crate::host::home_config() {
+        return Ok(format!("{}#homeConfigurations.\"{}\"", flake, name));
+    }
+
+    let user = std::env::var("USER").unwrap_or_default();
+    let names: Vec<String> = serde_json::from_str(&eval_json(
+        &format!("{}#homeConfigurations", flake),
+        Some("builtins.attrNames"),
+    )?)?;
+    crate::host::local_home_configs()
+        .into_iter()
+        .find(|c| names.contains(c))
+        .map(|c| format!("{}#homeConfigurations.\"{}\"", flake, c))
+        .ok_or_else(|| anyhow!("no home configuration for {} found in {}", user, flake))
+}
+
+/// Evaluate the current NixOS or Home Manager configuration with `apply`, a function taking the
+/// `config`, `options` and `pkgs` of the configuration
+pub fn eval_configuration(home: bool, apply: &str) -> Result<String> {
+    // Home Manager as a NixOS module is evaluated as part of the NixOS configuration
+    if home && crate::host::other(true).is_none() && crate::homemodule::detected() {
+        let user = nix_string(&crate::homemodule::user());
+        return eval_json(
+            &configuration(false)?,
+            Some(&format!(
+                "c: ({}) {{ config = c.config.home-manager.users.{}; \
+                 options = c.options.home-manager.users.type.getSubOptions [ ]; pkgs = c.pkgs; }}",
+                apply, user
+            )),
+        );
+    }
+    eval_json(&configuration(home)?, Some(apply))
+}
+
+/// Nix expression mapping `attrs` to the derivation names of the configuration `c`'s package set,
+/// so overlays and pinned inputs produce the exact names that end up in the store
+pub fn attr_names_expr(attrs: &[String]) -> String {
+    format!(
+        "map (a: (c.pkgs.lib.attrByPath (c.pkgs.lib.splitString \".\" a) {{ }} c.pkgs).name or null) [ {} ]",
+        attrs
+            .iter()
+            .map(|x| nix_string(x))
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
@@ -0,0 +1,248 @@
+use crate::{
+    config, edit, files,
+    flake::{eval_configuration, flake_dir, nix_string},
+    homemodule, host, is_system_configured,
+    scope::Scope,
+    search::{self, lookup},
+    store,
+};
+use anyhow::{Context, Result, anyhow};
+use libsnow::{Package, PackageAttr, metadata::Metadata};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Whether any of `paths`, the outputs of a package, installs fonts
+fn has_fonts(paths: &[PathBuf]) -> bool {
+    paths.iter().any(|x| x.join("share/fonts").is_dir())
+}
+
+/// What nixpkgs tells about a package that marks it as a font
+#[derive(Deserialize, Debug)]
+struct Info {
+    out: Option<String>,
+    position: Option<String>,
+    /// Whether it is under the SIL Open Font License
+    ofl: Option<bool>,
+}
+
+impl Info {
+    /// Fonts have `share/fonts` in their output when it is in the store already, are licensed
+    /// under the OFL or are kept with the fonts in `pkgs/data/fonts`
+    fn is_font(&self) -> bool {
+        self.out
+            .as_ref()
+            .is_some_and(|x| has_fonts(&[PathBuf::from(x)]))
+            || self.ofl == Some(true)
+            || self
+                .position
+                .as_ref()
+                .is_some_and(|x| x.contains("/pkgs/data/fonts/"))
+    }
+}
+
+const FONTS_EXPR: &str = r#"c:
+  let
+    try = x: let r = builtins.tryEval x; in if r.success then r.value else null;
+    lib = c.pkgs.lib;
+    info = p: {
+      out = try (if p ? outPath then builtins.unsafeDiscardStringContext p.outPath else null);
+      position = try (p.meta.position or null);
+      ofl = try (builtins.any (l: lib.hasPrefix "OFL" (l.spdxId or ""))
+        (lib.toList (p.meta.license or [ ])));
+    };
+  in
+  map (a: let p = lib.attrByPath (lib.splitString "." a) null c.pkgs; in
+    if builtins.isAttrs p then info p else { out = null; position = null; ofl = null; })"#;
+
+/// Which attributes are fonts, kept until the flake lock of the configuration changes
+fn cache_path() -> PathBuf {
+    config::cache_dir().join("fonts.json")
+}
+
+/// Attributes of `attrs` that are fonts in the nixpkgs of the configured flake. Packages are
+/// only evaluated, nothing is built, and only attributes not in the cache are evaluated.
+/// Without a NixOS or Home Manager configuration there is no nixpkgs to ask and nothing is a font.
+pub fn fonts_of(attrs: &[String]) -> Result<Vec<String>> {
+    let home = !is_system_configured();
+    let Some(file) = (if home {
+        config::home_config_file()
+    } else {
+        config::system_config_file()
+    }) else {
+        return Ok(vec![]);
+    };
+
+    let modified = |p: &Path| p.metadata().and_then(|m| m.modified()).ok();
+    let lock = flake_dir(&file).and_then(|x| modified(&x.join("flake.lock")));
+    let path = cache_path();
+    let mut cache: HashMap<String, bool> = match modified(&path) {
+        Some(t) if lock.is_none_or(|lock| t >= lock) => std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default(),
+        _ => HashMap::new(),
+    };
+
+    let missing = attrs
+        .iter()
+        .filter(|x| !cache.contains_key(*x))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        let apply = format!(
+            "{} [ {} ]",
+            FONTS_EXPR,
+            missing
+                .iter()
+                .map(|x| nix_string(x))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        let infos: Vec<Info> = serde_json::from_str(&eval_configuration(home, &apply)?)?;
+        cache.extend(missing.into_iter().zip(infos.iter().map(Info::is_font)));
+        std::fs::create_dir_all(config::cache_dir())?;
+        std::fs::write(&path, serde_json::to_string(&cache)?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(attrs
+        .iter()
+        .filter(|x| cache.get(*x) == Some(&true))
+        .cloned()
+        .collect())
+}
+
+/// File holding `fonts.packages` of the NixOS configuration
+fn system_file(file: Option<&str>) -> Result<PathBuf> {
+    if let Some(file) = file {
+        return files::resolve(file, Scope::System);
+    }
+    match host::other(false) {
+        Some(name) => host::package_file(&name, false),
+        None => config::system_config_file()
+            .ok_or_else(|| anyhow!("NixOS configuration file is not set, run `snow init`")),
+    }
+}
+
+/// File and option of the Home Manager package list, and the option enabling fontconfig
+fn home_target(file: Option<&str>) -> Result<(PathBuf, String, String)> {
+    if homemodule::detected() {
+        if let Some(file) = file {
+            let user = homemodule::user();
+            return Ok((
+                files::resolve(file, Scope::System)?,
+                homemodule::user_option(&user, "home.packages"),
+                homemodule::user_option(&user, "fonts.fontconfig.enable"),
+            ));
+        }
+        let declaration = homemodule::declaration()?;
+        return Ok((
+            declaration.file.clone(),
+            declaration.option("home.packages"),
+            declaration.option("fonts.fontconfig.enable"),
+        ));
+    }
+    let file = match (file, host::other(true)) {
+        (Some(file), _) => files::resolve(file, Scope::Home)?,
+        (None, Some(name)) => host::package_file(&name, true)?,
+        (None, None) => config::home_config_file().ok_or_else(|| {
+            anyhow!("Home Manager configuration file is not set, run `snow init`")
+        })?,
+    };
+    Ok((
+        file,
+        "home.packages".to_string(),
+        "fonts.fontconfig.enable".to_string(),
+    ))
+}
+
+/// Rebuild whatever configuration the font change went into
+async fn rebuild(home: bool) -> Result<()> {
+    if home && !homemodule::detected() {
+        files::rebuild(Scope::Home).await
+    } else {
+        files::rebuild(Scope::System).await
+    }
+}
+
+/// Install fonts into `fonts.packages`, or into the Home Manager packages with fontconfig enabled
+pub async fn install(
+    home: bool,
+    file: Option<&str>,
+    packages: &[&str],
+    md: &Metadata,
+) -> Result<()> {
+    search::validate(md, packages)?;
+    if home {
+        let (path, option, fontconfig) = home_target(file)?;
+        if file.is_some() {
+            files::create(&path, &option)?;
+        }
+        edit::add_packages(&path, &option, packages)?;
+        let content = edit::read(&path)?;
+        if nix_editor::read::readvalue(&content, &fontconfig)
+            .ok()
+            .as_deref()
+            != Some("true")
+        {
+            edit::set_option(&path, &fontconfig, "true")?;
+        }
+        if file.is_some() {
+            let scope = if homemodule::detected() {
+                Scope::System
+            } else {
+                Scope::Home
+            };
+            files::warn_unimported(&path, scope);
+        }
+    } else {
+        let path = system_file(file)?;
+        if file.is_some() {
+            files::create(&path, "fonts.packages")?;
+        }
+        edit::add_packages(&path, "fonts.packages", packages)?;
+        if file.is_some() {
+            files::warn_unimported(&path, Scope::System);
+        }
+    }
+    rebuild(home).await
+}
+
+pub async fn remove(home: bool, file: Option<&str>, packages: &[&str]) -> Result<()> {
+    if home {
+        let (file, option, _) = home_target(file)?;
+        edit::remove_packages(&file, &option, packages)?;
+    } else {
+        edit::remove_packages(&system_file(file)?, "fonts.packages", packages)?;
+    }
+    rebuild(home).await
+}
+
+/// Fonts in `fonts.packages` and font packages of Home Manager
+pub fn list(md: &Metadata) -> Result<Vec<Package>> {
+    let mut attrs = vec![];
+    if is_system_configured() {
+        let file = system_file(None)?;
+        // A configuration without fonts has no `fonts.packages`
+        if nix_editor::read::readvalue(&edit::read(&file)?, "fonts.packages").is_ok() {
+            attrs.extend(edit::list_packages(&file, "fonts.packages")?);
+        }
+    }
+    // Home Manager has no list of its own for fonts, its packages are told apart by their outputs
+    if Scope::Home.is_configured() {
+        for package in store::installed(Scope::Home, md).unwrap_or_default() {
+            if has_fonts(&package.paths) && !attrs.contains(&package.attr) {
+                attrs.push(package.attr);
+            }
+        }
+    }
+    Ok(attrs
+        .into_iter()
+        .map(|attr| Package {
+            version: lookup(md, &attr).and_then(|x| x.version),
+            attr: PackageAttr::NixPkgs { attr },
+        })
+        .collect())
+}
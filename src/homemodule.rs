@@ -0,0 +1,164 @@
+use crate::{
+    config, edit, files, host, is_system_configured,
+    scope::Scope,
+    search::{self, lookup},
+};
+use anyhow::{Result, anyhow};
+use libsnow::{Package, PackageAttr, metadata::Metadata};
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+pub fn user() -> String {
+    std::env::var("USER").unwrap_or_default()
+}
+
+/// Whether Home Manager is installed standalone, with its own profile
+fn standalone() -> bool {
+    Path::new(&format!(
+        "{}/.local/state/nix/profiles/home-manager",
+        std::env::var("HOME").unwrap_or_default()
+    ))
+    .is_symlink()
+}
+
+/// `home-manager.users.<name>.<option>`
+pub fn user_option(name: &str, option: &str) -> String {
+    format!("{}.{}", edit::attr("home-manager.users", name), option)
+}
+
+/// Place the Home Manager configuration of a user is declared in
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    /// File to edit
+    pub file: PathBuf,
+    /// Attribute the options are set under in `file`, empty when `file` is the Home Manager
+    /// module of the user itself
+    prefix: String,
+}
+
+impl Declaration {
+    /// Name of the Home Manager option `option` in `file`
+    pub fn option(&self, option: &str) -> String {
+        if self.prefix.is_empty() {
+            option.to_string()
+        } else {
+            format!("{}.{}", self.prefix, option)
+        }
+    }
+}
+
+/// File of the NixOS flake mentioning `home-manager.users.<name>`, the NixOS package file is
+/// checked first
+fn package_file(name: &str) -> Option<PathBuf> {
+    let base = match host::other(false) {
+        Some(host) => host::package_file(&host, false).ok()?,
+        None => config::system_config_file()?,
+    };
+    edit::declaring_file(&base, "home-manager.users", name)
+}
+
+/// Where `home-manager.users.<name>` is declared. An attribute set is edited in place, a module
+/// imported from another file (`home-manager.users.<name> = import ./home.nix;`) is edited in
+/// that file. Other values cannot be edited.
+fn find(name: &str) -> Result<Option<Declaration>> {
+    let Some(file) = package_file(name) else {
+        return Ok(None);
+    };
+    let attr = edit::attr("home-manager.users", name);
+    // Only options below the attribute are set, e.g. `home-manager.users.<name>.home.packages`
+    let Ok(value) = nix_editor::read::readvalue(&edit::read(&file)?, &attr) else {
+        return Ok(Some(Declaration { file, prefix: attr }));
+    };
+    let value = value.trim();
+    if value.starts_with('{') {
+        return Ok(Some(Declaration { file, prefix: attr }));
+    }
+    let path = value.strip_prefix("import ").unwrap_or(value).trim();
+    if path.starts_with("./") || path.starts_with("../") || path.starts_with('/') {
+        let mut module = file.parent().unwrap_or(Path::new("/")).join(path);
+        if module.is_dir() {
+            module = module.join("default.nix");
+        }
+        if module.is_file() {
+            return Ok(Some(Declaration {
+                file: module,
+                prefix: String::new(),
+            }));
+        }
+    }
+    Err(anyhow!(
+        "{} is set to `{}` in {}, which snow cannot edit, pass the Home Manager module of {} with --file",
+        attr,
+        value,
+        file.display(),
+        name
+    ))
+}
+
+/// Declaration of the current user, looked up once
+fn current() -> &'static Option<Result<Declaration, String>> {
+    static CURRENT: OnceLock<Option<Result<Declaration, String>>> = OnceLock::new();
+    CURRENT.get_or_init(|| {
+        if config::home_config_file().is_some() || standalone() || !is_system_configured() {
+            return None;
+        }
+        find(&user()).map_err(|e| e.to_string()).transpose()
+    })
+}
+
+/// Whether Home Manager of the current user is configured through the NixOS module
+/// instead of standalone
+pub fn detected() -> bool {
+    current().is_some()
+}
+
+/// Where the Home Manager configuration of the current user is declared
+pub fn declaration() -> Result<Declaration> {
+    match current() {
+        Some(Ok(x)) => Ok(x.clone()),
+        Some(Err(e)) => Err(anyhow!("{}", e)),
+        None => Err(anyhow!(
+            "no home-manager.users.{} found in the NixOS configuration",
+            user()
+        )),
+    }
+}
+
+/// Add `packages` to the Home Manager packages of the current user and rebuild the system
+pub async fn install(packages: &[&str], md: &Metadata) -> Result<()> {
+    search::validate(md, packages)?;
+    let declaration = declaration()?;
+    edit::add_packages(
+        &declaration.file,
+        &declaration.option("home.packages"),
+        packages,
+    )?;
+    files::rebuild(Scope::System).await
+}
+
+/// Remove `packages` from the Home Manager packages of the current user and rebuild the system
+pub async fn remove(packages: &[&str]) -> Result<()> {
+    let declaration = declaration()?;
+    edit::remove_packages(
+        &declaration.file,
+        &declaration.option("home.packages"),
+        packages,
+    )?;
+    files::rebuild(Scope::System).await
+}
+
+/// Home Manager packages of the current user declared in the NixOS configuration
+pub fn list(md: &Metadata) -> Result<Vec<Package>> {
+    let declaration = declaration()?;
+    Ok(
+        edit::list_packages(&declaration.file, &declaration.option("home.packages"))?
+            .into_iter()
+            .map(|attr| Package {
+                version: lookup(md, &attr).and_then(|x| x.version),
+                attr: PackageAttr::NixPkgs { attr },
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,210 @@
+use crate::{
+    config, edit,
+    flake::{flake_dir, hostname},
+    prompt,
+    search::{self, lookup},
+};
+use anyhow::{Context, Result, anyhow};
+use libsnow::{Package, PackageAttr, metadata::Metadata};
+use std::{path::PathBuf, process::Command, sync::OnceLock};
+
+static SELECTED: OnceLock<(Option<String>, Option<String>)> = OnceLock::new();
+static DEPLOY: OnceLock<Option<String>> = OnceLock::new();
+
+/// Select the configurations given on the command line, falling back to the config file.
+/// Other hosts are only deployed with `deploy` or to `target_host`.
+pub fn select(
+    host: Option<String>,
+    home_config: Option<String>,
+    deploy: bool,
+    target_host: Option<String>,
+) {
+    let settings = config::settings();
+    let _ = SELECTED.set((host.or(settings.host), home_config.or(settings.home_config)));
+    let _ = DEPLOY.set(target_host.or_else(|| deploy.then(self::host)));
+}
+
+/// Name of the selected NixOS configuration, the host name of this machine if none is selected
+pub fn host() -> String {
+    SELECTED
+        .get()
+        .and_then(|(host, _)| host.clone())
+        .unwrap_or_else(hostname)
+}
+
+/// Names the Home Manager configuration of the current user can have
+pub fn local_home_configs() -> [String; 2] {
+    let user = std::env::var("USER").unwrap_or_default();
+    [format!("{}@{}", user, hostname()), user]
+}
+
+/// Name of the selected Home Manager configuration, if one is selected
+pub fn home_config() -> Option<String> {
+    SELECTED.get().and_then(|(_, home)| home.clone())
+}
+
+/// Name of the selected configuration if it is not the one of this machine and user
+pub fn other(home: bool) -> Option<String> {
+    if home {
+        home_config().filter(|x| !local_home_configs().contains(x))
+    } else {
+        Some(host()).filter(|x| *x != hostname())
+    }
+}
+
+/// Fail if another configuration is selected, for commands that inspect the current generation
+pub fn require_local(home: bool) -> Result<()> {
+    match other(home) {
+        Some(name) => Err(anyhow!(
+            "{} is not the configuration of this machine, only its own generation can be inspected",
+            name
+        )),
+        None => Ok(()),
+    }
+}
+
+/// File listing the packages of the configuration `name`, from `[snow.hosts]`/`[snow.homes]`
+/// or the layouts multi-host flakes commonly use
+pub fn package_file(name: &str, home: bool) -> Result<PathBuf> {
+    let settings = config::settings();
+    let files = if home {
+        &settings.homes
+    } else {
+        &settings.hosts
+    };
+    if let Some(file) = files.get(name) {
+        return Ok(file.clone());
+    }
+
+    let base = config::system_config_file()
+        .or_else(config::home_config_file)
+        .ok_or_else(|| anyhow!("configuration file is not set, run `snow init`"))?;
+    let flake =
+        flake_dir(&base).ok_or_else(|| anyhow!("no flake.nix found above {}", base.display()))?;
+    let candidates = if home {
+        vec![
+            format!("homes/{}/default.nix", name),
+            format!("homes/{}/home.nix", name),
+            format!("homes/{}.nix", name),
+            format!("home/{}.nix", name),
+            format!("users/{}/home.nix", name),
+        ]
+    } else {
+        vec![
+            format!("hosts/{}/configuration.nix", name),
+            format!("hosts/{}/default.nix", name),
+            format!("hosts/{}.nix", name),
+            format!("machines/{}/configuration.nix", name),
+            format!("machines/{}/default.nix", name),
+        ]
+    };
+    let mut found = candidates
+        .into_iter()
+        .map(|x| flake.join(x))
+        .find(|x| x.is_file());
+    // Snowfall lib keeps configurations in `systems/<system>/<name>` and `homes/<system>/<name>`
+    if found.is_none() {
+        found = std::fs::read_dir(flake.join(if home { "homes" } else { "systems" }))
+            .into_iter()
+            .flat_map(|x| x.flatten())
+            .map(|x| x.path().join(name).join("default.nix"))
+            .find(|x| x.is_file());
+    }
+    found.ok_or_else(|| {
+        anyhow!(
+            "no package file found for {}, set it with `snow config set snow.{}.{} <path>`",
+            name,
+            if home { "homes" } else { "hosts" },
+            name
+        )
+    })
+}
+
+fn option(home: bool) -> &'static str {
+    if home {
+        "home.packages"
+    } else {
+        "environment.systemPackages"
+    }
+}
+
+/// Add `packages` to the package list of the configuration `name`
+pub fn install(name: &str, home: bool, packages: &[&str], md: &Metadata) -> Result<()> {
+    search::validate(md, packages)?;
+    let file = package_file(name, home)?;
+    edit::add_packages(&file, option(home), packages)
+}
+
+/// Remove `packages` from the package list of the configuration `name`
+pub fn remove(name: &str, home: bool, packages: &[&str]) -> Result<()> {
+    let file = package_file(name, home)?;
+    edit::remove_packages(&file, option(home), packages)
+}
+
+/// Packages declared in the configuration `name`
+pub fn list(name: &str, home: bool, md: &Metadata) -> Result<Vec<Package>> {
+    let file = package_file(name, home)?;
+    Ok(edit::list_packages(&file, option(home))?
+        .into_iter()
+        .map(|attr| Package {
+            version: lookup(md, &attr).and_then(|x| x.version),
+            attr: PackageAttr::NixPkgs { attr },
+        })
+        .collect())
+}
+
+/// Build the configuration `name`. NixOS configurations of other hosts are only deployed
+/// over SSH when asked to with `--deploy` or `--target-host` and after confirming, Home Manager
+/// configurations of other users are only built.
+pub fn rebuild(name: &str, home: bool) -> Result<()> {
+    let file = package_file(name, home)?;
+    let flake = flake_dir(&file)
+        .or_else(|| config::system_config_file().and_then(|x| flake_dir(&x)))
+        .ok_or_else(|| anyhow!("no flake.nix found above {}", file.display()))?;
+    let flake = flake.to_string_lossy();
+
+    let mut cmd = if home {
+        println!(
+            "{} is not the configuration of the current user, only building it",
+            name
+        );
+        let mut cmd = Command::new("nix");
+        cmd.args([
+            "build",
+            "--no-link",
+            &format!(
+                "{}#homeConfigurations.\"{}\".activationPackage",
+                flake, name
+            ),
+        ]);
+        cmd
+    } else if let Some(target) = DEPLOY.get().cloned().flatten() {
+        if !prompt::confirm(&format!("Deploy {} to {}?", name, target)) {
+            return Err(anyhow!("deployment of {} cancelled", name));
+        }
+        let mut cmd = Command::new("nixos-rebuild");
+        cmd.args([
+            "switch",
+            "--flake",
+            &format!("{}#{}", flake, name),
+            "--target-host",
+            &target,
+            "--use-remote-sudo",
+        ]);
+        cmd
+    } else {
+        println!(
+            "{} is not the configuration of this machine, only building it, \
+             use --deploy or --target-host <address> to deploy it",
+            name
+        );
+        let mut cmd = Command::new("nixos-rebuild");
+        cmd.args(["build", "--flake", &format!("{}#{}", flake, name)]);
+        cmd
+    };
+    let status = cmd.status().context("failed to run rebuild")?;
+    if !status.success() {
+        return Err(anyhow!("failed to rebuild {}", name));
+    }
+    Ok(())
+}
@@ -0,0 +1,207 @@
+use crate::{
+    WARNINGSTYLE, config,
+    flake::{eval_json, flake_dir, hostname, nix_files},
+    prompt,
+};
+use anyhow::{Result, anyhow};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use std::path::{Path, PathBuf};
+
+/// Flake found while looking for configurations
+#[derive(Debug, Clone)]
+pub struct FlakeInfo {
+    pub dir: PathBuf,
+    pub nixos: Vec<String>,
+    pub home: Vec<String>,
+}
+
+/// Directories configurations are commonly kept in
+fn candidate_dirs() -> Vec<PathBuf> {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let mut dirs = vec![];
+    // Flakes of an existing configuration come first
+    for file in [config::system_config_file(), config::home_config_file()]
+        .into_iter()
+        .flatten()
+    {
+        dirs.extend(flake_dir(&file));
+    }
+    dirs.push(PathBuf::from("/etc/nixos"));
+    for dir in [
+        ".config/nixos",
+        ".config/home-manager",
+        ".config/nix-config",
+        "nixos",
+        "nix-config",
+        "dotfiles",
+        ".dotfiles",
+    ] {
+        dirs.push(Path::new(&home).join(dir));
+    }
+    let mut found: Vec<PathBuf> = vec![];
+    for dir in dirs {
+        if dir.join("flake.nix").exists() && !found.contains(&dir) {
+            found.push(dir);
+        }
+    }
+    found
+}
+
+/// Names of the `output` configurations of the flake in `dir`
+fn configuration_names(dir: &Path, output: &str) -> Vec<String> {
+    eval_json(
+        &format!("{}#{}", dir.display(), output),
+        Some("builtins.attrNames"),
+    )
+    .ok()
+    .and_then(|x| serde_json::from_str(&x).ok())
+    .unwrap_or_default()
+}
+
+pub fn detect_flakes() -> Vec<FlakeInfo> {
+    candidate_dirs()
+        .into_iter()
+        .map(|dir| FlakeInfo {
+            nixos: configuration_names(&dir, "nixosConfigurations"),
+            home: configuration_names(&dir, "homeConfigurations"),
+            dir,
+        })
+        .filter(|x| !x.nixos.is_empty() || !x.home.is_empty())
+        .collect()
+}
+
+/// File of the flake in `dir` declaring `option`, falling back to the first of `defaults` that exists
+fn find_file(dir: &Path, option: &str, defaults: &[&str]) -> Option<PathBuf> {
+    let mut files = vec![];
+    nix_files(dir, &mut files);
+    files.sort();
+    files
+        .into_iter()
+        .find(|x| {
+            std::fs::read_to_string(x)
+                .unwrap_or_default()
+                .lines()
+                .any(|l| l.split('#').next().unwrap_or_default().contains(option))
+        })
+        .or_else(|| defaults.iter().map(|x| dir.join(x)).find(|x| x.exists()))
+}
+
+/// Ask for a path, proposing `proposed`; an empty answer keeps the proposal and `-` skips the value
+fn ask_path(name: &str, proposed: Option<&Path>) -> Option<PathBuf> {
+    let answer = match proposed {
+        Some(path) => prompt::ask(&format!(
+            "{} [{}] (enter to accept, - to skip):",
+            name,
+            path.display()
+        )),
+        None => prompt::ask(&format!("{} (enter to skip):", name)),
+    };
+    match answer.as_str() {
+        "" => proposed.map(Path::to_path_buf),
+        "-" => None,
+        path => Some(PathBuf::from(path)),
+    }
+}
+
+fn warn(message: &str) {
+    println!(
+        "{} {}",
+        "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+        message
+    );
+}
+
+pub fn init() -> Result<()> {
+    let flakes = detect_flakes();
+    let host = hostname();
+    let user = std::env::var("USER").unwrap_or_default();
+
+    if flakes.is_empty() {
+        warn("no flake with nixosConfigurations or homeConfigurations found");
+    }
+    for flake in &flakes {
+        println!("Found flake {}", flake.dir.display());
+        if !flake.nixos.is_empty() {
+            println!("  nixosConfigurations: {}", flake.nixos.join(", "));
+        }
+        if !flake.home.is_empty() {
+            println!("  homeConfigurations: {}", flake.home.join(", "));
+        }
+    }
+    println!("Host name: {}", host);
+
+    let system_flake = flakes
+        .iter()
+        .find(|x| x.nixos.contains(&host))
+        .or_else(|| flakes.iter().find(|x| !x.nixos.is_empty()));
+    if let Some(flake) = system_flake
+        && !flake.nixos.contains(&host)
+    {
+        warn(&format!(
+            "{} has no nixosConfigurations.\"{}\", system rebuilds will not find this host",
+            flake.dir.display(),
+            host
+        ));
+    }
+    let home_names = [format!("{}@{}", user, host), user.clone()];
+    let home_flake = flakes
+        .iter()
+        .find(|x| x.home.iter().any(|n| home_names.contains(n)))
+        .or_else(|| flakes.iter().find(|x| !x.home.is_empty()));
+    if let Some(flake) = home_flake
+        && !flake.home.iter().any(|n| home_names.contains(n))
+    {
+        warn(&format!(
+            "{} has no homeConfigurations.\"{}\" or homeConfigurations.\"{}\"",
+            flake.dir.display(),
+            home_names[0],
+            home_names[1]
+        ));
+    }
+
+    let system = system_flake.and_then(|x| {
+        find_file(
+            &x.dir,
+            "environment.systemPackages",
+            &["configuration.nix", "flake.nix"],
+        )
+    });
+    let home = home_flake.and_then(|x| find_file(&x.dir, "home.packages", &["home.nix"]));
+
+    println!();
+    let system = ask_path("NixOS configuration file", system.as_deref());
+    let home = ask_path("Home Manager configuration file", home.as_deref());
+    if system.is_none() && home.is_none() {
+        return Err(anyhow!("no configuration file selected"));
+    }
+    for path in system.iter().chain(home.iter()) {
+        if !path.exists() {
+            return Err(anyhow!("{} does not exist", path.display()));
+        }
+    }
+
+    // Keep settings that are already there
+    let mut configfile = libsnow::config::configfile::get_config().unwrap_or_default();
+    let path = libsnow::config::configfile::get_config_path()?;
+    println!("\nProposed configuration for {}:", path.display());
+    for (key, value, field) in [
+        (
+            "system_config_file",
+            &system,
+            &mut configfile.system_config_file,
+        ),
+        ("home_config_file", &home, &mut configfile.home_config_file),
+    ] {
+        *field = value.as_ref().map(|value| {
+            let value = value.canonicalize().unwrap_or(value.to_path_buf());
+            println!("  {} = \"{}\"", key, value.display());
+            value.to_string_lossy().to_string()
+        });
+    }
+    if !prompt::confirm("Write configuration?") {
+        return Ok(());
+    }
+    libsnow::config::configfile::set_config(&configfile)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
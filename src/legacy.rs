@@ -0,0 +1,174 @@
+use crate::{WARNINGSTYLE, search::lookup, store::split_name};
+use anyhow::{Context, Result, anyhow};
+use libsnow::{Package, PackageAttr, metadata::Metadata};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path, process::Command};
+
+/// Package of a nix-env profile, as printed by `nix-env -q --json`
+#[derive(Deserialize, Debug, Clone)]
+pub struct LegacyPackage {
+    pub name: String,
+    #[serde(default)]
+    pub pname: String,
+    #[serde(default)]
+    pub version: String,
+}
+
+/// Whether `~/.nix-profile` is a nix-env profile that `nix profile` has not taken over yet
+pub fn detected() -> bool {
+    let Ok(home) = std::env::var("HOME") else {
+        return false;
+    };
+    Path::new(&format!("{}/.nix-profile/manifest.nix", home)).exists()
+        && !Path::new(&format!("{}/.nix-profile/manifest.json", home)).exists()
+}
+
+fn nix_env(args: &[&str]) -> Result<()> {
+    let status = Command::new("nix-env")
+        .args(args)
+        .status()
+        .context("failed to run nix-env")?;
+    if !status.success() {
+        return Err(anyhow!("nix-env {} failed", args.join(" ")));
+    }
+    Ok(())
+}
+
+/// Packages installed with nix-env
+pub fn packages() -> Result<Vec<LegacyPackage>> {
+    let output = Command::new("nix-env")
+        .args(["--query", "--json"])
+        .output()
+        .context("failed to run nix-env")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "failed to query nix-env profile: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let packages: HashMap<String, LegacyPackage> = serde_json::from_slice(&output.stdout)?;
+    let mut packages = packages
+        .into_values()
+        .map(|mut x| {
+            // Older versions of nix-env only print the full name
+            if x.pname.is_empty() {
+                let (pname, version) = split_name(&x.name);
+                x.pname = pname;
+                x.version = version.unwrap_or_default();
+            }
+            x
+        })
+        .collect::<Vec<_>>();
+    packages.sort_by(|a, b| a.pname.cmp(&b.pname));
+    Ok(packages)
+}
+
+/// nixpkgs attribute of a nix-env package, if one with the same name exists
+pub fn nixpkgs_attr(package: &LegacyPackage, md: &Metadata) -> Option<String> {
+    lookup(md, &package.pname).map(|x| x.attribute)
+}
+
+pub fn list(md: &Metadata) -> Result<Vec<Package>> {
+    Ok(packages()?
+        .into_iter()
+        .map(|x| Package {
+            attr: PackageAttr::NixPkgs {
+                attr: nixpkgs_attr(&x, md).unwrap_or(x.pname),
+            },
+            version: Some(x.version).filter(|x| !x.is_empty()),
+        })
+        .collect())
+}
+
+/// Install nixpkgs attributes into the nix-env profile from `<nixpkgs>`, so that
+/// `nix-env --upgrade` keeps upgrading them. The channel is not named `nixpkgs` everywhere
+/// (it is `nixos` on NixOS), the search path finds it either way.
+pub fn install(packages: &[&str]) -> Result<()> {
+    let mut args = vec!["--file", "<nixpkgs>", "--install", "--attr"];
+    args.extend(packages);
+    nix_env(&args)
+}
+
+/// Names nix-env knows the installed `packages` by
+fn names(packages: &[&str]) -> Result<Vec<String>> {
+    let installed = self::packages()?;
+    packages
+        .iter()
+        .map(|x| {
+            installed
+                .iter()
+                .find(|y| y.pname == *x || y.name == *x)
+                .map(|y| y.name.clone())
+                .ok_or_else(|| anyhow!("{} is not installed in the nix-env profile", x))
+        })
+        .collect()
+}
+
+pub fn remove(packages: &[&str]) -> Result<()> {
+    let names = names(packages)?;
+    let mut args = vec!["--uninstall"];
+    args.extend(names.iter().map(String::as_str));
+    nix_env(&args)
+}
+
+/// Upgrade `packages`, or every package if none are given
+pub fn update(packages: &[&str]) -> Result<()> {
+    if packages.is_empty() {
+        return nix_env(&["--upgrade"]);
+    }
+    let names = names(packages)?;
+    let mut args = vec!["--upgrade"];
+    args.extend(names.iter().map(String::as_str));
+    nix_env(&args)
+}
+
+/// Move the nix-env profile to `nix profile`. Packages found in nixpkgs are reinstalled from the
+/// nixpkgs flake, the others are kept by their store paths.
+pub async fn convert(dry_run: bool) -> Result<()> {
+    if !detected() {
+        return Err(anyhow!("~/.nix-profile is not a nix-env profile"));
+    }
+    let md = Metadata::connect().await?;
+    let packages = packages()?;
+    let mut mapped = vec![];
+    for package in &packages {
+        match nixpkgs_attr(package, &md) {
+            Some(attr) => {
+                println!("{} -> nixpkgs#{}", package.name, attr);
+                mapped.push((package.name.clone(), attr));
+            }
+            None => println!(
+                "{} {} has no nixpkgs attribute, keeping its store path",
+                "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+                package.name
+            ),
+        }
+    }
+    if dry_run {
+        return Ok(());
+    }
+    if mapped.is_empty() {
+        return Err(anyhow!(
+            "none of the packages are in nixpkgs, reinstall them with `nix profile install`"
+        ));
+    }
+
+    // `nix profile` takes over the profile on its first change, keeping what nix-env left as
+    // store paths. Remove the mapped packages first so they come back as flake installables.
+    let mut args = vec!["--uninstall"];
+    args.extend(mapped.iter().map(|(name, _)| name.as_str()));
+    nix_env(&args)?;
+    let status = Command::new("nix")
+        .args(["profile", "install"])
+        .args(mapped.iter().map(|(_, attr)| format!("nixpkgs#{}", attr)))
+        .status()
+        .context("failed to run nix profile")?;
+    if !status.success() {
+        nix_env(&["--rollback"])?;
+        return Err(anyhow!(
+            "nix profile failed, the nix-env profile was rolled back"
+        ));
+    }
+    Ok(())
+}
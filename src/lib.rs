@@ -14,7 +14,34 @@ lazy_static::lazy_static! {
         .bold();
 }
 
+pub mod binaries;
+pub mod config;
+pub mod dedupe;
+pub mod doctor;
+pub mod drift;
+pub mod edit;
+pub mod files;
+pub mod flake;
+pub mod fonts;
+pub mod homemodule;
+pub mod host;
+pub mod init;
+pub mod legacy;
+pub mod list;
+pub mod manifest;
+pub mod migrate;
+pub mod options;
+pub mod profiles;
+pub mod prompt;
+pub mod run;
+pub mod scope;
 pub mod search;
+pub mod shell;
+pub mod size;
+pub mod store;
+pub mod users;
+pub mod which;
+pub mod why;
 
 pub fn is_system_configured() -> bool {
     if let Ok(config) = libsnow::config::configfile::get_config() {
@@ -39,3 +66,22 @@ pub fn is_profile_configured() -> bool {
         false
     }
 }
+
+/// Whether Home Manager is installed, standalone or as a NixOS module
+pub fn home_manager_installed() -> bool {
+    if homemodule::detected() {
+        return true;
+    }
+    let config = libsnow::config::configfile::get_config();
+    if let Ok(config) = config
+        && config.home_config_file.is_some()
+    {
+        return true;
+    }
+
+    Path::new(&format!(
+        "{}/.local/state/nix/profiles/home-manager",
+        std::env::var("HOME").unwrap().as_str()
+    ))
+    .is_symlink()
+}
@@ -0,0 +1,141 @@
+use crate::{
+    VERSIONSTYLE,
+    scope::{Scope, attr_name},
+    search::lookup,
+};
+use anyhow::Result;
+use libsnow::{Package, metadata::Metadata};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Machine readable output formats of `snow list`
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListFormat {
+    Json,
+    Tsv,
+    Table,
+}
+
+/// Installed package in machine readable form
+#[derive(Serialize, Debug, Clone)]
+pub struct Entry {
+    pub scope: Scope,
+    pub attribute: String,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    pub store_path: Option<String>,
+}
+
+/// Print `lst` under the heading `title` in the human readable format
+pub fn printlist(title: &str, mut lst: Vec<Package>) {
+    lst.sort_by_key(|x| x.attr.to_string());
+    println!("{}", title.if_supports_color(Stdout, |t| t.bright_cyan()));
+    for pkg in lst {
+        if let Some(v) = pkg.version.filter(|x| !x.is_empty()) {
+            println!(
+                "{} ({})",
+                pkg.attr,
+                v.if_supports_color(Stdout, |t| t.style(*VERSIONSTYLE))
+            );
+        } else {
+            println!("{}", pkg.attr);
+        }
+    }
+}
+
+/// Store paths of the elements of the selected profile, keyed by attribute
+pub fn profile_store_paths() -> HashMap<String, String> {
+    let mut paths = HashMap::new();
+    for element in crate::profiles::elements(&crate::profiles::root()).unwrap_or_default() {
+        let Some(path) = element.store_path else {
+            continue;
+        };
+        for key in [element.attr, element.name].into_iter().flatten() {
+            paths.insert(key, path.clone());
+        }
+    }
+    paths
+}
+
+pub fn entries(scope: Scope, lst: Vec<Package>, md: &Metadata) -> Vec<Entry> {
+    let store_paths = if scope == Scope::Profile {
+        profile_store_paths()
+    } else {
+        HashMap::new()
+    };
+    let mut entries = lst
+        .into_iter()
+        .map(|pkg| {
+            let attribute = pkg.attr.to_string();
+            let name = attr_name(&attribute);
+            Entry {
+                scope,
+                description: lookup(md, &name).and_then(|x| x.description),
+                store_path: store_paths.get(&name).cloned(),
+                attribute,
+                version: pkg.version,
+            }
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.attribute.cmp(&b.attribute));
+    entries
+}
+
+fn field(value: &Option<String>) -> String {
+    value
+        .as_deref()
+        .unwrap_or_default()
+        .replace(['\t', '\n'], " ")
+}
+
+pub fn print(entries: &[Entry], format: ListFormat) -> Result<()> {
+    match format {
+        ListFormat::Json => println!("{}", serde_json::to_string_pretty(entries)?),
+        ListFormat::Tsv => {
+            println!("scope\tattribute\tversion\tdescription\tstore_path");
+            for e in entries {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    e.scope,
+                    e.attribute,
+                    field(&e.version),
+                    field(&e.description),
+                    field(&e.store_path)
+                );
+            }
+        }
+        ListFormat::Table => {
+            let rows = entries
+                .iter()
+                .map(|e| {
+                    [
+                        e.scope.to_string(),
+                        e.attribute.clone(),
+                        field(&e.version),
+                        field(&e.description),
+                        field(&e.store_path),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            let header =
+                ["SCOPE", "ATTRIBUTE", "VERSION", "DESCRIPTION", "STORE PATH"].map(String::from);
+            let mut widths = header.clone().map(|x| x.chars().count());
+            for row in &rows {
+                for (w, col) in widths.iter_mut().zip(row) {
+                    *w = (*w).max(col.chars().count());
+                }
+            }
+            for row in std::iter::once(&header).chain(&rows) {
+                let line = row
+                    .iter()
+                    .zip(widths)
+                    .map(|(col, w)| format!("{:w$}", col, w = w))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                println!("{}", line.trim_end());
+            }
+        }
+    }
+    Ok(())
+}
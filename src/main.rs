@@ -1,12 +1,12 @@
 use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
 use libsnow::nixos::AuthMethod;
 use nix_snow::{
-    ERRORSTYLE, VERSIONSTYLE, WARNINGSTYLE, is_home_configured, is_profile_configured,
-    is_system_configured,
+    ERRORSTYLE, WARNINGSTYLE, home_manager_installed, is_system_configured, list::ListFormat,
+    scope::Scope, size::SizeSort,
 };
 use owo_colors::{OwoColorize, Stream::Stdout};
 use std::{
-    path::Path,
+    path::PathBuf,
     process::{Command, Stdio, exit},
 };
 
@@ -17,25 +17,69 @@ struct Cli {
     /// Show version information
     #[clap(short = 'V', long)]
     version: bool,
+    /// NixOS configuration to act on, defaults to `snow.host` in the config or this machine
+    #[arg(long, global = true)]
+    host: Option<String>,
+    /// Home Manager configuration to act on, e.g. alice@lab1
+    #[arg(long, global = true)]
+    home_config: Option<String>,
+    /// Deploy the NixOS configuration of another host to it over SSH instead of only building it
+    #[arg(long, global = true)]
+    deploy: bool,
+    /// Deploy the NixOS configuration of another host to this address, implies --deploy
+    #[arg(long, global = true)]
+    target_host: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    #[command(group(ArgGroup::new("install").args(&["system", "home"])))]
+    #[command(group(ArgGroup::new("install").args(&["system", "home", "user_packages"])))]
+    #[command(group(ArgGroup::new("install_target").args(&["system", "home", "user_packages", "font"]).multiple(true)))]
     Install {
         packages: Vec<String>,
         #[arg(short, long)]
         system: bool,
         #[arg(short, long)]
         home: bool,
+        /// Packages of a NixOS user, the current user if no name is given
+        #[arg(long, value_name = "USER", num_args = 0..=1, require_equals = true)]
+        user_packages: Option<Option<String>>,
+        /// Nix file, or category from `snow.files`, holding the package list
+        #[arg(long, requires = "install_target")]
+        file: Option<String>,
+        /// Font packages, kept in `fonts.packages` or with Home Manager's fontconfig
+        #[arg(long, conflicts_with = "user_packages")]
+        font: bool,
+        /// Named profile to use instead of the default one
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["install", "font"])]
+        profile: Option<String>,
+        /// Profile at a custom path to use instead of the default one
+        #[arg(long, conflicts_with_all = ["profile", "install", "font"])]
+        profile_path: Option<PathBuf>,
     },
-    #[command(group(ArgGroup::new("remove").args(&["system", "home"])))]
+    #[command(group(ArgGroup::new("remove").args(&["system", "home", "user_packages"])))]
+    #[command(group(ArgGroup::new("remove_target").args(&["system", "home", "user_packages", "font"]).multiple(true)))]
     Remove {
         packages: Vec<String>,
         #[arg(short, long)]
         system: bool,
         #[arg(short, long)]
         home: bool,
+        /// Packages of a NixOS user, the current user if no name is given
+        #[arg(long, value_name = "USER", num_args = 0..=1, require_equals = true)]
+        user_packages: Option<Option<String>>,
+        /// Nix file, or category from `snow.files`, holding the package list
+        #[arg(long, requires = "remove_target")]
+        file: Option<String>,
+        /// Font packages, kept in `fonts.packages` or with Home Manager's fontconfig
+        #[arg(long, conflicts_with = "user_packages")]
+        font: bool,
+        /// Named profile to use instead of the default one
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["remove", "font"])]
+        profile: Option<String>,
+        /// Profile at a custom path to use instead of the default one
+        #[arg(long, conflicts_with_all = ["profile", "remove", "font"])]
+        profile_path: Option<PathBuf>,
     },
     #[command(group(ArgGroup::new("update").args(&["system", "home", "all"])))]
     Update {
@@ -46,6 +90,12 @@ enum Commands {
         home: bool,
         #[arg(short, long)]
         all: bool,
+        /// Named profile to use instead of the default one
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["system", "home"])]
+        profile: Option<String>,
+        /// Profile at a custom path to use instead of the default one
+        #[arg(long, conflicts_with_all = ["profile", "system", "home"])]
+        profile_path: Option<PathBuf>,
     },
     #[command(group(ArgGroup::new("rebuild").args(&["system", "home"])))]
     Rebuild {
@@ -54,21 +104,287 @@ enum Commands {
         #[arg(short, long)]
         home: bool,
     },
-    #[command(group(ArgGroup::new("listtype").args(&["profile", "system", "home"])))]
+    #[command(group(ArgGroup::new("listtype").args(&["profile", "system", "home", "user_packages", "font"])))]
     List {
-        #[arg(short, long)]
-        profile: bool,
+        /// Only list the profile, or the named profile if a name is given
+        #[arg(short, long, value_name = "NAME", num_args = 0..=1, require_equals = true)]
+        profile: Option<Option<String>>,
+        /// Only list the profile at a custom path
+        #[arg(long, conflicts_with_all = ["profile", "system", "home", "user_packages"])]
+        profile_path: Option<PathBuf>,
         #[arg(short, long)]
         system: bool,
         #[arg(short, long)]
         home: bool,
+        /// Packages of a NixOS user, the current user if no name is given
+        #[arg(
+            long,
+            value_name = "USER",
+            num_args = 0..=1,
+            require_equals = true,
+            conflicts_with_all = ["format", "size", "binaries"]
+        )]
+        user_packages: Option<Option<String>>,
+        /// Only list fonts
+        #[arg(long, conflicts_with_all = ["format", "size", "binaries"])]
+        font: bool,
+        /// Only show packages installed in more than one scope
+        #[arg(long, conflicts_with = "listtype")]
+        duplicates: bool,
+        /// Print packages in a machine readable format
+        #[arg(long, value_enum, conflicts_with = "duplicates")]
+        format: Option<ListFormat>,
+        /// Show the closure size of each package
+        #[arg(long, conflicts_with_all = ["duplicates", "format"])]
+        size: bool,
+        /// Order of packages when showing sizes
+        #[arg(long, value_enum, default_value_t, requires = "size")]
+        sort: SizeSort,
+        /// Show the executables each package provides
+        #[arg(long, conflicts_with_all = ["duplicates", "format", "size"])]
+        binaries: bool,
     },
     Search {
         query: Vec<String>,
+        /// Print results as JSON
+        #[arg(long)]
+        json: bool,
     },
     Run {
         package: String,
         arguments: Vec<String>,
+        /// Executable to run instead of the package's main program
+        #[arg(long)]
+        bin: Option<String>,
+        /// List the executables the package provides
+        #[arg(long, conflicts_with_all = ["bin", "keep"])]
+        list_bins: bool,
+        /// Install the package after the program exits, asking for the scope if none is given
+        #[arg(long, value_enum, num_args = 0..=1, require_equals = true)]
+        keep: Option<Option<Scope>>,
+        /// Named profile to use instead of the default one
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["list_bins"], requires = "keep")]
+        profile: Option<String>,
+        /// Profile at a custom path to use instead of the default one
+        #[arg(long, conflicts_with_all = ["profile", "list_bins"], requires = "keep")]
+        profile_path: Option<PathBuf>,
+    },
+    /// Start a shell with packages available, or run a command in it
+    Shell {
+        /// Attributes or flake references
+        #[arg(required = true)]
+        packages: Vec<String>,
+        /// Command to run instead of an interactive shell
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+    /// Enable a NixOS service or Home Manager program
+    #[command(group(ArgGroup::new("enable").args(&["system", "home"])))]
+    Enable {
+        /// Option path without `.enable`, e.g. services.openssh
+        option: String,
+        #[arg(short, long)]
+        system: bool,
+        #[arg(short, long)]
+        home: bool,
+    },
+    /// Disable a NixOS service or Home Manager program
+    #[command(group(ArgGroup::new("disable").args(&["system", "home"])))]
+    Disable {
+        /// Option path without `.enable`, e.g. services.openssh
+        option: String,
+        #[arg(short, long)]
+        system: bool,
+        #[arg(short, long)]
+        home: bool,
+    },
+    /// Set a NixOS or Home Manager option
+    #[command(group(ArgGroup::new("set").args(&["system", "home"])))]
+    Set {
+        option: String,
+        /// Nix expression, e.g. false or '"Europe/Berlin"'
+        value: String,
+        #[arg(short, long)]
+        system: bool,
+        #[arg(short, long)]
+        home: bool,
+    },
+    /// Move packages from one scope to another
+    Move {
+        #[arg(required = true)]
+        packages: Vec<String>,
+        #[arg(long, value_enum)]
+        from: Scope,
+        #[arg(long, value_enum)]
+        to: Scope,
+    },
+    /// Move all imperative profile packages into the system or home configuration
+    Migrate {
+        #[arg(long, value_enum)]
+        to: nix_snow::migrate::Target,
+        /// Only show how profile packages would be mapped
+        #[arg(long)]
+        dry_run: bool,
+        /// Named profile to migrate instead of the default one
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+        /// Profile at a custom path to migrate instead of the default one
+        #[arg(long, conflicts_with = "profile")]
+        profile_path: Option<PathBuf>,
+    },
+    /// Remove packages installed in more than one scope
+    Dedupe {
+        /// Scopes to keep packages in, most preferred first (defaults to `dedupe_prefer` in the config)
+        #[arg(long, value_enum, value_delimiter = ',')]
+        prefer: Vec<Scope>,
+        /// Only show what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print a manifest of the packages installed in every scope
+    Export,
+    /// Install the packages of a manifest that are missing
+    Import {
+        manifest: PathBuf,
+        /// Only show what would be installed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Bring installed packages in line with a manifest
+    Sync {
+        manifest: PathBuf,
+        /// Make the changes instead of only showing them
+        #[arg(long)]
+        apply: bool,
+        /// Never remove packages missing from the manifest
+        #[arg(long)]
+        additive_only: bool,
+    },
+    /// Compare declared packages with the current generation
+    #[command(group(ArgGroup::new("drift").args(&["system", "home"])))]
+    Drift {
+        #[arg(short, long)]
+        system: bool,
+        #[arg(short, long)]
+        home: bool,
+    },
+    /// Show the closure size of installed packages
+    Size {
+        #[arg(required = true)]
+        packages: Vec<String>,
+        #[arg(long, value_enum, default_value_t)]
+        sort: SizeSort,
+    },
+    /// Explain why a package or store path is present
+    Why {
+        /// Attribute or store path
+        target: String,
+    },
+    /// Show commands provided by more than one installed package
+    Conflicts,
+    /// Show which package and scope a command on PATH comes from
+    Which { command: String },
+    /// Check the environment for common problems
+    Doctor,
+    /// Create the configuration file from the detected flake
+    Init,
+    /// View and change the configuration file
+    ///
+    /// `SNOW_CONFIG` only moves the file of the settings only snow uses, libsnow always reads
+    /// `system_config_file` and `home_config_file` from its own fixed path.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Manage the nix profile
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
+    },
+    /// Search and inspect NixOS and Home Manager options
+    Options {
+        #[command(subcommand)]
+        command: OptionsCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum OptionsCommands {
+    /// Search options by name and description
+    #[command(group(ArgGroup::new("search").args(&["system", "home"])))]
+    Search {
+        query: Vec<String>,
+        #[arg(short, long)]
+        system: bool,
+        #[arg(short, long)]
+        home: bool,
+        /// Print results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the declaration of an option
+    #[command(group(ArgGroup::new("show").args(&["system", "home"])))]
+    Show {
+        option: String,
+        #[arg(short, long)]
+        system: bool,
+        #[arg(short, long)]
+        home: bool,
+        /// Print the option as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rebuild the local option index
+    #[command(group(ArgGroup::new("update").args(&["system", "home"])))]
+    Update {
+        #[arg(short, long)]
+        system: bool,
+        #[arg(short, long)]
+        home: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfileCommands {
+    /// Create a named profile with packages
+    Create {
+        name: String,
+        #[arg(required = true)]
+        packages: Vec<String>,
+    },
+    /// List named profiles
+    List,
+    /// Delete a named profile
+    Delete { name: String },
+    /// Point ~/.nix-profile to a named profile, `default` restores the default profile
+    Activate { name: String },
+    /// Convert a nix-env profile to a nix profile
+    Convert {
+        /// Only show how packages would be mapped
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Print the configuration file
+    Show,
+    /// Print the value of a key
+    Get { key: String },
+    /// Set a key, lists are comma separated and an empty value removes the key
+    Set { key: String, value: String },
+    /// Open the configuration file in $EDITOR
+    Edit {
+        /// Edit the file of the settings only snow uses instead, `SNOW_CONFIG` overrides it
+        #[arg(long)]
+        settings: bool,
+    },
+    /// Print the path of the configuration file
+    Path {
+        /// Print the path of the file of the settings only snow uses instead, `SNOW_CONFIG` overrides it
+        #[arg(long)]
+        settings: bool,
     },
 }
 
@@ -94,19 +410,40 @@ async fn main() {
         exit(0);
     }
 
+    nix_snow::host::select(cli.host, cli.home_config, cli.deploy, cli.target_host);
+
     if let Some(command) = cli.command {
         match command {
             Commands::Install {
                 packages,
                 system,
                 home,
+                user_packages,
+                file,
+                font,
+                profile,
+                profile_path,
             } => {
-                if system {
+                select_profile(profile, profile_path);
+                if font {
                     let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
-                    let md = libsnow::metadata::Metadata::connect().await.unwrap();
-                    if let Err(e) =
-                        libsnow::nixos::install::install(&p, &md, libsnow::nixos::AuthMethod::Sudo)
-                            .await
+                    if home {
+                        check_home_manager();
+                    }
+                    let md = metadata().await;
+                    if let Err(e) = nix_snow::fonts::install(home, file.as_deref(), &p, &md).await {
+                        eprintln!(
+                            "{} {}",
+                            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                            e
+                        );
+                        exit(1)
+                    }
+                } else if let Some(user) = user_packages {
+                    let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
+                    let user = nix_snow::users::name(user);
+                    let md = metadata().await;
+                    if let Err(e) = nix_snow::users::install(&user, file.as_deref(), &p, &md).await
                     {
                         eprintln!(
                             "{} {}",
@@ -115,13 +452,30 @@ async fn main() {
                         );
                         exit(1)
                     }
+                } else if system {
+                    let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
+                    let md = libsnow::metadata::Metadata::connect().await.unwrap();
+                    let res = match &file {
+                        Some(file) => nix_snow::files::install(file, Scope::System, &p, &md).await,
+                        None => nix_snow::scope::install(Scope::System, &p, &md).await,
+                    };
+                    if let Err(e) = res {
+                        eprintln!(
+                            "{} {}",
+                            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                            e
+                        );
+                        exit(1)
+                    }
                 } else if home {
                     check_home_manager();
                     let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
                     let md = libsnow::metadata::Metadata::connect().await.unwrap();
-                    if let Err(e) =
-                        libsnow::homemanager::install::install(&p, &md, AuthMethod::Sudo).await
-                    {
+                    let res = match &file {
+                        Some(file) => nix_snow::files::install(file, Scope::Home, &p, &md).await,
+                        None => nix_snow::scope::install(Scope::Home, &p, &md).await,
+                    };
+                    if let Err(e) = res {
                         eprintln!(
                             "{} {}",
                             "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
@@ -131,7 +485,8 @@ async fn main() {
                     }
                 } else {
                     let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
-                    if let Err(e) = libsnow::profile::install::install(&p).await {
+                    let md = metadata().await;
+                    if let Err(e) = nix_snow::scope::install(Scope::Profile, &p, &md).await {
                         eprintln!(
                             "{} {}",
                             "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
@@ -152,14 +507,45 @@ async fn main() {
                 packages,
                 system,
                 home,
+                user_packages,
+                file,
+                font,
+                profile,
+                profile_path,
             } => {
-                if system {
+                select_profile(profile, profile_path);
+                if font {
+                    let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
+                    if home {
+                        check_home_manager();
+                    }
+                    if let Err(e) = nix_snow::fonts::remove(home, file.as_deref(), &p).await {
+                        eprintln!(
+                            "{} {}",
+                            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                            e
+                        );
+                        exit(1)
+                    }
+                } else if let Some(user) = user_packages {
+                    let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
+                    let user = nix_snow::users::name(user);
+                    if let Err(e) = nix_snow::users::remove(&user, file.as_deref(), &p).await {
+                        eprintln!(
+                            "{} {}",
+                            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                            e
+                        );
+                        exit(1)
+                    }
+                } else if system {
                     let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
                     let md = libsnow::metadata::Metadata::connect().await.unwrap();
-                    if let Err(e) =
-                        libsnow::nixos::remove::remove(&p, &md, libsnow::nixos::AuthMethod::Sudo)
-                            .await
-                    {
+                    let res = match &file {
+                        Some(file) => nix_snow::files::remove(file, Scope::System, &p).await,
+                        None => nix_snow::scope::remove(Scope::System, &p, &md).await,
+                    };
+                    if let Err(e) = res {
                         eprintln!(
                             "{} {}",
                             "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
@@ -171,9 +557,11 @@ async fn main() {
                     check_home_manager();
                     let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
                     let md = libsnow::metadata::Metadata::connect().await.unwrap();
-                    if let Err(e) =
-                        libsnow::homemanager::remove::remove(&p, &md, AuthMethod::Sudo).await
-                    {
+                    let res = match &file {
+                        Some(file) => nix_snow::files::remove(file, Scope::Home, &p).await,
+                        None => nix_snow::scope::remove(Scope::Home, &p, &md).await,
+                    };
+                    if let Err(e) = res {
                         eprintln!(
                             "{} {}",
                             "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
@@ -183,7 +571,8 @@ async fn main() {
                     }
                 } else {
                     let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
-                    if let Err(e) = libsnow::profile::remove::remove(&p).await {
+                    let md = metadata().await;
+                    if let Err(e) = nix_snow::scope::remove(Scope::Profile, &p, &md).await {
                         eprintln!(
                             "{} {}",
                             "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
@@ -205,7 +594,10 @@ async fn main() {
                 system,
                 home,
                 all,
+                profile,
+                profile_path,
             } => {
+                select_profile(profile, profile_path);
                 if all {
                     // System upgrade updates all packages
                     if packages.is_some() {
@@ -225,8 +617,8 @@ async fn main() {
                         );
                         exit(1)
                     }
-                    if is_profile_configured()
-                        && let Err(e) = libsnow::profile::update::update_all().await
+                    if Scope::Profile.is_configured()
+                        && let Err(e) = nix_snow::scope::update_profile(&[]).await
                     {
                         eprintln!(
                             "{} {}",
@@ -262,7 +654,13 @@ async fn main() {
                             "warning:".if_supports_color(Stdout, |t| t.bright_yellow())
                         );
                     }
-                    if let Err(e) = libsnow::homemanager::update::update(AuthMethod::Sudo).await {
+                    // Home Manager as a NixOS module is updated with the system
+                    let res = if nix_snow::homemodule::detected() {
+                        libsnow::nixos::update::update(AuthMethod::Sudo).await
+                    } else {
+                        libsnow::homemanager::update::update(AuthMethod::Sudo).await
+                    };
+                    if let Err(e) = res {
                         eprintln!(
                             "{} {}",
                             "error:"
@@ -271,18 +669,20 @@ async fn main() {
                         );
                         exit(1)
                     }
-                } else if let Some(pkgs) = packages {
-                    let _ = libsnow::profile::update::update(
-                        &pkgs.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+                } else {
+                    let p = packages.unwrap_or_default();
+                    if let Err(e) = nix_snow::scope::update_profile(
+                        &p.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
                     )
-                    .await;
-                } else if let Err(e) = libsnow::profile::update::update_all().await {
-                    eprintln!(
-                        "{} {}",
-                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
-                        e
-                    );
-                    exit(1)
+                    .await
+                    {
+                        eprintln!(
+                            "{} {}",
+                            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                            e
+                        );
+                        exit(1)
+                    }
                 }
                 if let Err(e) = libsnow::utils::misc::refresh_icons() {
                     eprintln!(
@@ -293,7 +693,16 @@ async fn main() {
                 }
             }
             Commands::Rebuild { system, home } => {
-                if system || !home {
+                if let Some(name) = nix_snow::host::other(home) {
+                    if let Err(e) = nix_snow::host::rebuild(&name, home) {
+                        eprintln!(
+                            "{} {}",
+                            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                            e
+                        );
+                        exit(1)
+                    }
+                } else if system || !home {
                     if let Err(e) =
                         libsnow::nixos::rebuild::rebuild(libsnow::nixos::AuthMethod::Sudo).await
                     {
@@ -306,10 +715,7 @@ async fn main() {
                     }
                 } else if home {
                     check_home_manager();
-                    if let Err(e) =
-                        libsnow::homemanager::rebuild::rebuild(libsnow::nixos::AuthMethod::Sudo)
-                            .await
-                    {
+                    if let Err(e) = nix_snow::files::rebuild(Scope::Home).await {
                         eprintln!(
                             "{} {}",
                             "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
@@ -321,63 +727,38 @@ async fn main() {
             }
             Commands::List {
                 profile,
+                profile_path,
                 system,
                 home,
+                user_packages,
+                font,
+                duplicates,
+                format,
+                size,
+                sort,
+                binaries,
             } => {
-                fn printprofilelist(mut lst: Vec<libsnow::Package>) {
-                    lst.sort_by(|a, b| a.attr.to_string().cmp(&b.attr.to_string()));
-                    println!(
-                        "{}",
-                        "Profile Packages:".if_supports_color(Stdout, |t| t.bright_cyan())
-                    );
-                    for pkg in lst {
-                        println!(
-                            "{} ({})",
-                            pkg.attr,
-                            pkg.version
-                                .unwrap_or_default()
-                                .if_supports_color(Stdout, |t| t.style(*VERSIONSTYLE))
-                        );
+                let profile = match profile {
+                    Some(name) => {
+                        select_profile(name, profile_path);
+                        true
                     }
-                }
-                fn printsystemlist(mut lst: Vec<libsnow::Package>) {
-                    lst.sort_by(|a, b| a.attr.to_string().cmp(&b.attr.to_string()));
-                    println!(
-                        "{}",
-                        "System Packages:".if_supports_color(Stdout, |t| t.bright_cyan())
-                    );
-                    for pkg in lst {
-                        if let Some(v) = pkg.version {
-                            println!(
-                                "{} ({})",
-                                pkg.attr,
-                                v.if_supports_color(Stdout, |t| t.style(*VERSIONSTYLE))
-                            );
-                        } else {
-                            println!("{}", pkg.attr);
-                        }
-                    }
-                }
-                fn printhomelist(mut lst: Vec<libsnow::Package>) {
-                    lst.sort_by(|a, b| a.attr.to_string().cmp(&b.attr.to_string()));
-                    println!(
-                        "{}",
-                        "Home Manager Packages:".if_supports_color(Stdout, |t| t.bright_cyan())
-                    );
-                    for pkg in lst {
-                        println!(
-                            "{} ({})",
-                            pkg.attr,
-                            pkg.version
-                                .unwrap_or_default()
-                                .if_supports_color(Stdout, |t| t.style(*VERSIONSTYLE))
-                        );
+                    None => {
+                        let custom = profile_path.is_some();
+                        select_profile(None, profile_path);
+                        custom
                     }
-                }
-                if profile {
-                    let lst = libsnow::profile::list::list();
-                    match lst {
-                        Ok(lst) => printprofilelist(lst),
+                };
+                // Listing a profile reads the profile itself, only connect to the metadata
+                // database for the listings that need it
+                if profile
+                    && format.is_none()
+                    && !size
+                    && !binaries
+                    && let Some(res) = nix_snow::scope::list_profile()
+                {
+                    match res {
+                        Ok(lst) => nix_snow::list::printlist(Scope::Profile.title(), lst),
                         Err(e) => {
                             eprintln!(
                                 "{} {}",
@@ -387,11 +768,14 @@ async fn main() {
                             exit(1);
                         }
                     }
-                } else if system {
-                    let md = libsnow::metadata::Metadata::connect().await.unwrap();
-                    let lst = libsnow::nixos::list::list_systempackages(&md);
-                    match lst {
-                        Ok(lst) => printsystemlist(lst),
+                    exit(0);
+                }
+                let md = metadata().await;
+                if duplicates {
+                    nix_snow::dedupe::print(&nix_snow::dedupe::find(&md));
+                } else if font {
+                    match nix_snow::fonts::list(&md) {
+                        Ok(lst) => nix_snow::list::printlist("Fonts:", lst),
                         Err(e) => {
                             eprintln!(
                                 "{} {}",
@@ -401,12 +785,12 @@ async fn main() {
                             exit(1);
                         }
                     }
-                } else if home {
-                    check_home_manager();
-                    let md = libsnow::metadata::Metadata::connect().await.unwrap();
-                    let lst = libsnow::homemanager::list::list(&md);
-                    match lst {
-                        Ok(lst) => printhomelist(lst),
+                } else if let Some(user) = user_packages {
+                    let user = nix_snow::users::name(user);
+                    match nix_snow::users::list(&user, &md) {
+                        Ok(lst) => {
+                            nix_snow::list::printlist(&format!("User Packages ({}):", user), lst)
+                        }
                         Err(e) => {
                             eprintln!(
                                 "{} {}",
@@ -417,35 +801,73 @@ async fn main() {
                         }
                     }
                 } else {
-                    let md = libsnow::metadata::Metadata::connect().await.unwrap();
-                    let mut printed_first = false;
-                    if is_profile_configured() {
-                        let lst = libsnow::profile::list::list();
-                        match lst {
-                            Ok(lst) => {
-                                printprofilelist(lst);
-                                printed_first = true;
+                    let scopes = if profile {
+                        vec![Scope::Profile]
+                    } else if system {
+                        vec![Scope::System]
+                    } else if home {
+                        check_home_manager();
+                        vec![Scope::Home]
+                    } else {
+                        Scope::ALL
+                            .into_iter()
+                            .filter(Scope::is_configured)
+                            .collect()
+                    };
+                    let mut entries = vec![];
+                    let mut lists = vec![];
+                    for (i, scope) in scopes.into_iter().enumerate() {
+                        if binaries {
+                            match nix_snow::store::installed(scope, &md) {
+                                Ok(installed) => {
+                                    if i > 0 {
+                                        println!();
+                                    }
+                                    nix_snow::binaries::print(scope, &installed);
+                                }
+                                Err(e) => {
+                                    eprintln!(
+                                        "{} {}",
+                                        "error:"
+                                            .if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                                        e
+                                    );
+                                    exit(1);
+                                }
                             }
-                            Err(e) => {
-                                eprintln!(
-                                    "{} {}",
-                                    "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
-                                    e
-                                );
-                                exit(1);
+                            continue;
+                        }
+                        if size {
+                            match nix_snow::size::sizes(scope, &md) {
+                                Ok(sizes) => {
+                                    if i > 0 {
+                                        println!();
+                                    }
+                                    nix_snow::size::print(&sizes, sort);
+                                }
+                                Err(e) => {
+                                    eprintln!(
+                                        "{} {}",
+                                        "error:"
+                                            .if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                                        e
+                                    );
+                                    exit(1);
+                                }
                             }
+                            continue;
                         }
-                    }
-                    if is_system_configured() {
-                        let syslst = libsnow::nixos::list::list_systempackages(&md);
-                        match syslst {
+                        match nix_snow::scope::list(scope, &md) {
                             Ok(lst) => {
-                                if printed_first {
-                                    println!();
+                                if format.is_some() {
+                                    entries.extend(nix_snow::list::entries(scope, lst, &md));
                                 } else {
-                                    printed_first = true;
+                                    if i > 0 {
+                                        println!();
+                                    }
+                                    lists.push((scope, lst.clone()));
+                                    nix_snow::list::printlist(scope.title(), lst);
                                 }
-                                printsystemlist(lst);
                             }
                             Err(e) => {
                                 eprintln!(
@@ -457,28 +879,40 @@ async fn main() {
                             }
                         }
                     }
-                    if home_manager_installed() && is_home_configured() {
-                        let homelst = libsnow::homemanager::list::list(&md);
-                        match homelst {
-                            Ok(homelst) => {
-                                if printed_first {
-                                    println!();
-                                }
-                                printhomelist(homelst);
-                            }
-                            Err(e) => {
-                                eprintln!(
-                                    "{} {}",
-                                    "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
-                                    e
+                    if let Some(format) = format {
+                        if let Err(e) = nix_snow::list::print(&entries, format) {
+                            eprintln!(
+                                "{} {}",
+                                "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                                e
+                            );
+                            exit(1);
+                        }
+                    } else if !size && !binaries && !profile && !system && !home {
+                        if is_system_configured() {
+                            let user = nix_snow::users::name(None);
+                            if let Ok(lst) = nix_snow::users::list(&user, &md)
+                                && !lst.is_empty()
+                            {
+                                println!();
+                                nix_snow::list::printlist(
+                                    &format!("User Packages ({}):", user),
+                                    lst,
                                 );
-                                exit(1);
                             }
                         }
+                        if let Ok(lst) = nix_snow::fonts::list(&md)
+                            && !lst.is_empty()
+                        {
+                            println!();
+                            nix_snow::list::printlist("Fonts:", lst);
+                        }
+                        // Reuse the lists printed above instead of fetching every scope again
+                        nix_snow::dedupe::warn(&nix_snow::dedupe::find_in(&lists));
                     }
                 }
             }
-            Commands::Search { query } => {
+            Commands::Search { query, json } => {
                 if query.is_empty() {
                     println!(
                         "{} No search query provided",
@@ -487,7 +921,7 @@ async fn main() {
                     exit(1);
                 }
                 let query: Vec<&str> = query.iter().map(|x| &**x).collect();
-                if let Err(e) = nix_snow::search::search(&query).await {
+                if let Err(e) = nix_snow::search::search(&query, json).await {
                     eprintln!(
                         "{} {}",
                         "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
@@ -496,13 +930,416 @@ async fn main() {
                     exit(1)
                 };
             }
-            Commands::Run { package, arguments } => {
-                if let Err(e) = libsnow::profile::run::run(
+            Commands::Run {
+                package,
+                arguments,
+                bin,
+                list_bins,
+                keep,
+                profile,
+                profile_path,
+            } => {
+                select_profile(profile, profile_path);
+                if list_bins {
+                    if let Err(e) = nix_snow::run::list_bins(&package).await {
+                        eprintln!(
+                            "{} {}",
+                            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                            e
+                        );
+                        exit(1)
+                    }
+                    exit(0)
+                }
+                match nix_snow::run::run(
                     &package,
+                    bin.as_deref(),
                     &arguments.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
                 )
                 .await
                 {
+                    Ok(0) => {}
+                    // Pass on the exit code of the program, there is nothing to keep
+                    Ok(code) => exit(code),
+                    Err(e) => {
+                        eprintln!(
+                            "{} {}",
+                            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                            e
+                        );
+                        exit(1)
+                    }
+                }
+                if let Some(scope) = keep {
+                    if scope == Some(Scope::Home) {
+                        check_home_manager();
+                    }
+                    if let Err(e) = nix_snow::run::keep(&package, scope).await {
+                        eprintln!(
+                            "{} {}",
+                            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                            e
+                        );
+                        exit(1)
+                    }
+                    if let Err(e) = libsnow::utils::misc::refresh_icons() {
+                        eprintln!(
+                            "{} failed to refresh icons: {}",
+                            "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+                            e
+                        );
+                    }
+                }
+            }
+            Commands::Shell { packages, command } => {
+                let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
+                let c: Vec<&str> = command.iter().map(|x| &**x).collect();
+                match nix_snow::shell::shell(&p, &c).await {
+                    Ok(code) => exit(code),
+                    Err(e) => {
+                        eprintln!(
+                            "{} {}",
+                            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                            e
+                        );
+                        exit(1)
+                    }
+                }
+            }
+            Commands::Enable {
+                option,
+                system: _,
+                home,
+            } => {
+                if home {
+                    check_home_manager();
+                }
+                if let Err(e) =
+                    nix_snow::options::set(&format!("{}.enable", option), "true", home).await
+                {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+            }
+            Commands::Disable {
+                option,
+                system: _,
+                home,
+            } => {
+                if home {
+                    check_home_manager();
+                }
+                if let Err(e) =
+                    nix_snow::options::set(&format!("{}.enable", option), "false", home).await
+                {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+            }
+            Commands::Set {
+                option,
+                value,
+                system: _,
+                home,
+            } => {
+                if home {
+                    check_home_manager();
+                }
+                if let Err(e) = nix_snow::options::set(&option, &value, home).await {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+            }
+            Commands::Move { packages, from, to } => {
+                if from == Scope::Home || to == Scope::Home {
+                    check_home_manager();
+                }
+                let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
+                if let Err(e) = nix_snow::scope::move_packages(&p, from, to).await {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+                if let Err(e) = libsnow::utils::misc::refresh_icons() {
+                    eprintln!(
+                        "{} failed to refresh icons: {}",
+                        "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+                        e
+                    );
+                }
+            }
+            Commands::Migrate {
+                to,
+                dry_run,
+                profile,
+                profile_path,
+            } => {
+                select_profile(profile, profile_path);
+                if to == nix_snow::migrate::Target::Home {
+                    check_home_manager();
+                }
+                if let Err(e) = nix_snow::migrate::migrate(to, dry_run).await {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+                if let Err(e) = libsnow::utils::misc::refresh_icons() {
+                    eprintln!(
+                        "{} failed to refresh icons: {}",
+                        "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+                        e
+                    );
+                }
+            }
+            Commands::Dedupe { prefer, dry_run } => {
+                if let Err(e) = nix_snow::dedupe::dedupe(&prefer, dry_run).await {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+                if let Err(e) = libsnow::utils::misc::refresh_icons() {
+                    eprintln!(
+                        "{} failed to refresh icons: {}",
+                        "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+                        e
+                    );
+                }
+            }
+            Commands::Export => {
+                if let Err(e) = nix_snow::manifest::export().await {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+            }
+            Commands::Import { manifest, dry_run } => {
+                if let Err(e) = nix_snow::manifest::import(&manifest, dry_run).await {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+                if let Err(e) = libsnow::utils::misc::refresh_icons() {
+                    eprintln!(
+                        "{} failed to refresh icons: {}",
+                        "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+                        e
+                    );
+                }
+            }
+            Commands::Sync {
+                manifest,
+                apply,
+                additive_only,
+            } => {
+                if let Err(e) = nix_snow::manifest::sync(&manifest, apply, additive_only).await {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+                if apply && let Err(e) = libsnow::utils::misc::refresh_icons() {
+                    eprintln!(
+                        "{} failed to refresh icons: {}",
+                        "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+                        e
+                    );
+                }
+            }
+            Commands::Drift { system, home } => {
+                let scopes = if system {
+                    vec![Scope::System]
+                } else if home {
+                    check_home_manager();
+                    vec![Scope::Home]
+                } else {
+                    [Scope::System, Scope::Home]
+                        .into_iter()
+                        .filter(Scope::is_configured)
+                        .collect()
+                };
+                let md = libsnow::metadata::Metadata::connect().await.unwrap();
+                for (i, scope) in scopes.into_iter().enumerate() {
+                    match nix_snow::drift::drift(scope, &md) {
+                        Ok(drift) => {
+                            if i > 0 {
+                                println!();
+                            }
+                            nix_snow::drift::print(&drift);
+                        }
+                        Err(e) => {
+                            eprintln!(
+                                "{} {}",
+                                "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                                e
+                            );
+                            exit(1);
+                        }
+                    }
+                }
+            }
+            Commands::Size { packages, sort } => {
+                let p: Vec<&str> = packages.iter().map(|x| &**x).collect();
+                if let Err(e) = nix_snow::size::size(&p, sort).await {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+            }
+            Commands::Why { target } => {
+                if let Err(e) = nix_snow::why::why(&target).await {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+            }
+            Commands::Conflicts => {
+                if let Err(e) = nix_snow::binaries::print_conflicts().await {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+            }
+            Commands::Which { command } => {
+                if let Err(e) = nix_snow::which::which(&command).await {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+            }
+            Commands::Doctor => {
+                if !nix_snow::doctor::doctor().await {
+                    exit(1)
+                }
+            }
+            Commands::Init => {
+                if let Err(e) = nix_snow::init::init() {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+            }
+            Commands::Config { command } => {
+                let res = match command {
+                    ConfigCommands::Show => nix_snow::config::show(),
+                    ConfigCommands::Get { key } => nix_snow::config::get(&key),
+                    ConfigCommands::Set { key, value } => nix_snow::config::set(&key, &value),
+                    ConfigCommands::Edit { settings } => nix_snow::config::edit(settings),
+                    ConfigCommands::Path { settings: true } => {
+                        println!("{}", nix_snow::config::config_path().display());
+                        Ok(())
+                    }
+                    ConfigCommands::Path { settings: false } => {
+                        libsnow::config::configfile::get_config_path()
+                            .map(|x| println!("{}", x.display()))
+                    }
+                };
+                if let Err(e) = res {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+            }
+            Commands::Profile { command } => {
+                let res = match command {
+                    ProfileCommands::Create { name, packages } => {
+                        nix_snow::profiles::create(
+                            &name,
+                            &packages.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+                        )
+                        .await
+                    }
+                    ProfileCommands::List => nix_snow::profiles::print(),
+                    ProfileCommands::Delete { name } => nix_snow::profiles::delete(&name),
+                    ProfileCommands::Activate { name } => nix_snow::profiles::activate(&name),
+                    ProfileCommands::Convert { dry_run } => {
+                        nix_snow::legacy::convert(dry_run).await
+                    }
+                };
+                if let Err(e) = res {
+                    eprintln!(
+                        "{} {}",
+                        "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                        e
+                    );
+                    exit(1)
+                }
+            }
+            Commands::Options { command } => {
+                let res = match command {
+                    OptionsCommands::Search {
+                        query,
+                        system: _,
+                        home,
+                        json,
+                    } => {
+                        if query.is_empty() {
+                            println!(
+                                "{} No search query provided",
+                                "error:".if_supports_color(Stdout, |t| t.red())
+                            );
+                            exit(1);
+                        }
+                        let query: Vec<&str> = query.iter().map(|x| &**x).collect();
+                        nix_snow::options::search(&query, home, json)
+                    }
+                    OptionsCommands::Show {
+                        option,
+                        system: _,
+                        home,
+                        json,
+                    } => nix_snow::options::show(&option, home, json),
+                    OptionsCommands::Update { system: _, home } => {
+                        nix_snow::options::update_index(home).map(|_| ())
+                    }
+                };
+                if let Err(e) = res {
                     eprintln!(
                         "{} {}",
                         "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
@@ -517,27 +1354,37 @@ async fn main() {
     }
 }
 
-fn check_home_manager() {
-    if !home_manager_installed() {
+fn select_profile(profile: Option<String>, profile_path: Option<PathBuf>) {
+    if let Err(e) = nix_snow::profiles::select(profile, profile_path) {
         eprintln!(
-            "{} Home Manager is not installed. Please install it first.",
-            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE))
+            "{} {}",
+            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+            e
         );
         exit(1);
     }
 }
 
-fn home_manager_installed() -> bool {
-    let config = libsnow::config::configfile::get_config();
-    if let Ok(config) = config
-        && config.home_config_file.is_some()
-    {
-        return true;
+async fn metadata() -> libsnow::metadata::Metadata {
+    match libsnow::metadata::Metadata::connect().await {
+        Ok(md) => md,
+        Err(e) => {
+            eprintln!(
+                "{} failed to open the package metadata: {}",
+                "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE)),
+                e
+            );
+            exit(1);
+        }
     }
+}
 
-    Path::new(&format!(
-        "{}/.local/state/nix/profiles/home-manager",
-        std::env::var("HOME").unwrap().as_str()
-    ))
-    .is_symlink()
+fn check_home_manager() {
+    if !home_manager_installed() {
+        eprintln!(
+            "{} Home Manager is not installed. Please install it first.",
+            "error:".if_supports_color(Stdout, |t| t.style(*ERRORSTYLE))
+        );
+        exit(1);
+    }
 }
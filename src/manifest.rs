@@ -0,0 +1,238 @@
+use crate::{
+    PKGSTYLE,
+    scope::{self, Scope, attr_name},
+};
+use anyhow::{Context, Result, anyhow};
+use libsnow::metadata::Metadata;
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Packages installed across scopes, as written by `snow export`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Manifest {
+    pub version: u32,
+    #[serde(default, rename = "package")]
+    pub packages: Vec<ManifestPackage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestPackage {
+    /// Attribute path, e.g. `ripgrep` or `python3Packages.black`
+    pub attr: String,
+    pub scope: Scope,
+    /// Version installed when the manifest was written, informational only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Flake the package was installed from, when it is not the default nixpkgs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+}
+
+impl ManifestPackage {
+    /// Name to pass to the install functions of the package's scope
+    pub fn installable(&self) -> String {
+        match (&self.input, self.scope) {
+            (Some(input), Scope::Profile) => format!("{}#{}", input, self.attr),
+            _ => self.attr.clone(),
+        }
+    }
+}
+
+/// Collect the packages of every configured scope
+pub fn current(md: &Metadata) -> Result<Manifest> {
+    let mut packages = vec![];
+    for s in Scope::ALL.into_iter().filter(Scope::is_configured) {
+        let mut lst = scope::list(s, md)?
+            .into_iter()
+            .map(|pkg| {
+                let name = pkg.attr.to_string();
+                let input = name
+                    .split_once('#')
+                    .map(|(input, _)| input.to_string())
+                    .filter(|x| !["nixpkgs", "flake:nixpkgs"].contains(&x.as_str()));
+                ManifestPackage {
+                    attr: attr_name(&name),
+                    scope: s,
+                    version: pkg.version,
+                    input,
+                }
+            })
+            .collect::<Vec<_>>();
+        lst.sort_by(|a, b| a.attr.cmp(&b.attr));
+        packages.extend(lst);
+    }
+    Ok(Manifest {
+        version: MANIFEST_VERSION,
+        packages,
+    })
+}
+
+pub fn read(path: &Path) -> Result<Manifest> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let manifest: Manifest =
+        toml::from_str(&content).with_context(|| format!("failed to parse {}", path.display()))?;
+    if manifest.version > MANIFEST_VERSION {
+        return Err(anyhow!(
+            "{} uses manifest version {}, this snow supports up to {}",
+            path.display(),
+            manifest.version,
+            MANIFEST_VERSION
+        ));
+    }
+    Ok(manifest)
+}
+
+pub async fn export() -> Result<()> {
+    let md = Metadata::connect().await?;
+    print!("{}", toml::to_string(&current(&md)?)?);
+    Ok(())
+}
+
+/// Changes needed to bring one scope in line with a manifest
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub scope: Scope,
+    /// Installables missing from the scope
+    pub add: Vec<String>,
+    /// Installed packages not in the manifest, as named by the scope
+    pub remove: Vec<String>,
+    pub keep: Vec<String>,
+}
+
+/// Compare the manifest with the current state of every scope it mentions.
+/// Scopes without any package in the manifest are left alone.
+pub fn plan(manifest: &Manifest, md: &Metadata, additive_only: bool) -> Result<Vec<Plan>> {
+    let mut plans = vec![];
+    for s in Scope::ALL {
+        let wanted = manifest
+            .packages
+            .iter()
+            .filter(|x| x.scope == s)
+            .collect::<Vec<_>>();
+        if wanted.is_empty() {
+            continue;
+        }
+        if !s.is_configured() {
+            return Err(anyhow!(
+                "manifest contains {} packages but the {} scope is not configured",
+                s,
+                s
+            ));
+        }
+
+        let installed = scope::list(s, md)?
+            .into_iter()
+            .map(|x| x.attr.to_string())
+            .collect::<Vec<_>>();
+        let mut plan = Plan {
+            scope: s,
+            add: vec![],
+            remove: vec![],
+            keep: vec![],
+        };
+        for pkg in &wanted {
+            if installed.iter().any(|x| attr_name(x) == pkg.attr) {
+                plan.keep.push(pkg.attr.clone());
+            } else {
+                plan.add.push(pkg.installable());
+            }
+        }
+        if !additive_only {
+            plan.remove = installed
+                .into_iter()
+                .filter(|x| !wanted.iter().any(|y| y.attr == attr_name(x)))
+                .collect();
+        }
+        plans.push(plan);
+    }
+    Ok(plans)
+}
+
+pub fn print_plan(plans: &[Plan]) {
+    for plan in plans {
+        println!(
+            "{}",
+            format!("{} scope:", plan.scope).if_supports_color(Stdout, |t| t.bright_cyan())
+        );
+        for pkg in &plan.add {
+            println!(
+                "  {} {}",
+                "+".if_supports_color(Stdout, |t| t.bright_green()),
+                pkg.if_supports_color(Stdout, |t| t.style(*PKGSTYLE))
+            );
+        }
+        for pkg in &plan.remove {
+            println!(
+                "  {} {}",
+                "-".if_supports_color(Stdout, |t| t.bright_red()),
+                pkg.if_supports_color(Stdout, |t| t.style(*PKGSTYLE))
+            );
+        }
+        for pkg in &plan.keep {
+            println!("  = {}", pkg);
+        }
+    }
+}
+
+/// Install and remove packages according to `plans`, with one rebuild per scope
+pub async fn apply(plans: &[Plan], md: &Metadata) -> Result<()> {
+    for plan in plans {
+        if plan.add.is_empty() && plan.remove.is_empty() {
+            continue;
+        }
+        let add: Vec<&str> = plan.add.iter().map(|x| &**x).collect();
+        let remove: Vec<&str> = plan.remove.iter().map(|x| &**x).collect();
+        scope::change(plan.scope, &add, &remove, md).await?;
+    }
+    Ok(())
+}
+
+/// Install every package of the manifest at `path` that is missing, one install per scope
+pub async fn import(path: &Path, dry_run: bool) -> Result<()> {
+    let manifest = read(path)?;
+    let md = Metadata::connect().await?;
+    let plans = plan(&manifest, &md, true)?;
+    for plan in &plans {
+        for pkg in &plan.add {
+            println!(
+                "{} {} ({})",
+                if dry_run {
+                    "Would install"
+                } else {
+                    "Installing"
+                },
+                pkg.if_supports_color(Stdout, |t| t.style(*PKGSTYLE)),
+                plan.scope
+            );
+        }
+    }
+    if dry_run {
+        return Ok(());
+    }
+    apply(&plans, &md).await
+}
+
+/// Show, and with `apply` carry out, the changes needed to match the manifest at `path`
+pub async fn sync(path: &Path, apply_plan: bool, additive_only: bool) -> Result<()> {
+    let manifest = read(path)?;
+    let md = Metadata::connect().await?;
+    let plans = plan(&manifest, &md, additive_only)?;
+    print_plan(&plans);
+    if plans
+        .iter()
+        .all(|x| x.add.is_empty() && x.remove.is_empty())
+    {
+        println!("Already in sync");
+        return Ok(());
+    }
+    if !apply_plan {
+        println!();
+        println!("Run again with --apply to make these changes");
+        return Ok(());
+    }
+    apply(&plans, &md).await
+}
@@ -0,0 +1,89 @@
+use crate::{
+    PKGSTYLE, WARNINGSTYLE,
+    scope::{Scope, attr_name},
+    search::lookup,
+};
+use anyhow::{Result, anyhow};
+use libsnow::metadata::Metadata;
+use owo_colors::{OwoColorize, Stream::Stdout};
+
+/// Map a profile element back to a nixpkgs attribute
+pub fn nixpkgs_attr(element: &str, md: &Metadata) -> Option<String> {
+    lookup(md, &attr_name(element)).map(|x| x.attribute)
+}
+
+/// Declarative scope profile packages can be migrated to
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    System,
+    Home,
+}
+
+impl From<Target> for Scope {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::System => Scope::System,
+            Target::Home => Scope::Home,
+        }
+    }
+}
+
+/// Move every package of the selected profile into the declarative `to` scope
+pub async fn migrate(to: Target, dry_run: bool) -> Result<()> {
+    let to = Scope::from(to);
+    if !to.is_configured() {
+        return Err(anyhow!("{} scope is not configured", to));
+    }
+
+    let md = Metadata::connect().await?;
+    let declared = crate::scope::list(to, &md)?
+        .into_iter()
+        .map(|x| x.attr.to_string())
+        .collect::<Vec<_>>();
+
+    let mut elements = vec![];
+    let mut attrs = vec![];
+    // The profile scope covers named and nix-env profiles as well as the default one
+    for pkg in crate::scope::list(Scope::Profile, &md)? {
+        let element = pkg.attr.to_string();
+        match nixpkgs_attr(&element, &md) {
+            Some(attr) => {
+                println!(
+                    "{} -> {}",
+                    element,
+                    attr.if_supports_color(Stdout, |t| t.style(*PKGSTYLE))
+                );
+                if !declared.contains(&attr) && !attrs.contains(&attr) {
+                    attrs.push(attr);
+                }
+                elements.push(element);
+            }
+            None => eprintln!(
+                "{} skipping {}, no matching nixpkgs attribute",
+                "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+                element
+            ),
+        }
+    }
+
+    if elements.is_empty() {
+        println!("No profile packages to migrate");
+        return Ok(());
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    if !attrs.is_empty() {
+        let a: Vec<&str> = attrs.iter().map(|x| &**x).collect();
+        crate::scope::install(to, &a, &md).await.map_err(|e| {
+            anyhow!(
+                "failed to install into {} scope, profile is unchanged: {}",
+                to,
+                e
+            )
+        })?;
+    }
+    let e: Vec<&str> = elements.iter().map(|x| &**x).collect();
+    crate::scope::remove(Scope::Profile, &e, &md).await
+}
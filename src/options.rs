@@ -0,0 +1,255 @@
+use crate::{PKGSTYLE, WARNINGSTYLE, edit, flake, homemodule, host, search::highlight};
+use anyhow::{Context, Result, anyhow};
+use libsnow::nixos::AuthMethod;
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, time::SystemTime};
+
+/// Declaration of a single option, as stored in the local index
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OptionInfo {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub option_type: String,
+    pub default: Option<String>,
+    pub example: Option<String>,
+    pub description: Option<String>,
+    pub declarations: Vec<String>,
+}
+
+// Flattens the option tree of a configuration into a list of documented options.
+// Defaults and examples are evaluated lazily by the module system, so they are wrapped
+// in `tryEval` to keep a single broken default from failing the whole index.
+const OPTIONS_EXPR: &str = r#"c:
+  let
+    try = x: let r = builtins.tryEval x; in if r.success then r.value else null;
+    text = x: if builtins.isAttrs x then x.text or null else x;
+  in
+  map (o: {
+    inherit (o) name type;
+    description = try (text (o.description or null));
+    default = try (text (o.default or null));
+    example = try (text (o.example or null));
+    declarations = map toString (o.declarations or [ ]);
+  }) (builtins.filter (o: o.visible != false && !(o.internal or false))
+    (c.pkgs.lib.optionAttrSetToDocList c.options))"#;
+
+/// Check whether `option` is declared in the current NixOS or Home Manager configuration.
+/// Options below a submodule (e.g. `users.users.<name>`) are accepted once the submodule is reached.
+pub fn validate(option: &str, home: bool) -> Result<bool> {
+    let path = option
+        .split('.')
+        .map(flake::nix_string)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let apply = format!(
+        "c: let walk = s: ps: if (s._type or null) == \"option\" then true \
+         else if ps == [ ] then false \
+         else if builtins.isAttrs s && builtins.hasAttr (builtins.head ps) s \
+         then walk (builtins.getAttr (builtins.head ps) s) (builtins.tail ps) \
+         else false; in walk c.options [ {} ]",
+        path
+    );
+    let out = flake::eval_configuration(home, &apply)?;
+    Ok(out == "true")
+}
+
+/// Set `option` to the Nix expression `value` and rebuild the configuration
+pub async fn set(option: &str, value: &str, home: bool) -> Result<()> {
+    match validate(option, home) {
+        Ok(true) => {}
+        Ok(false) => return Err(anyhow!("option {} does not exist", option)),
+        Err(e) => eprintln!(
+            "{} could not validate {}: {}",
+            "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+            option,
+            e
+        ),
+    }
+
+    // Other hosts are edited in their own file and only built unless deployed
+    if let Some(name) = host::other(home) {
+        edit::set_option(&host::package_file(&name, home)?, option, value)?;
+        return host::rebuild(&name, home);
+    }
+
+    // Home Manager as a NixOS module is set through the NixOS configuration of the user
+    if home && homemodule::detected() {
+        let declaration = homemodule::declaration()?;
+        edit::set_option(&declaration.file, &declaration.option(option), value)?;
+        return libsnow::nixos::rebuild::rebuild(AuthMethod::Sudo).await;
+    }
+
+    let file = if home {
+        crate::config::home_config_file()
+    } else {
+        crate::config::system_config_file()
+    }
+    .ok_or_else(|| {
+        anyhow!(
+            "{} configuration file is not set",
+            if home { "Home Manager" } else { "NixOS" }
+        )
+    })?;
+
+    edit::set_option(&file, option, value)?;
+
+    if home {
+        libsnow::homemanager::rebuild::rebuild(AuthMethod::Sudo).await
+    } else {
+        libsnow::nixos::rebuild::rebuild(AuthMethod::Sudo).await
+    }
+}
+
+fn index_path(home: bool) -> PathBuf {
+    crate::config::cache_dir().join(if home {
+        "options-home.json"
+    } else {
+        "options-nixos.json"
+    })
+}
+
+/// Evaluate the options of the current configuration and store them in the local index
+pub fn update_index(home: bool) -> Result<Vec<OptionInfo>> {
+    let out = flake::eval_configuration(home, OPTIONS_EXPR)?;
+    let options: Vec<OptionInfo> =
+        serde_json::from_str(&out).context("failed to parse option declarations")?;
+    let path = index_path(home);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_string(&options)?)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(options)
+}
+
+/// Load the option index, rebuilding it when missing or older than the configuration
+pub fn index(home: bool) -> Result<Vec<OptionInfo>> {
+    let path = index_path(home);
+    let modified = |p: &PathBuf| p.metadata().and_then(|m| m.modified()).ok();
+    // Home Manager as a NixOS module changes with the NixOS configuration
+    let config = if home && !homemodule::detected() {
+        crate::config::home_config_file()
+    } else {
+        crate::config::system_config_file()
+    };
+    let lock = config
+        .as_deref()
+        .and_then(flake::flake_dir)
+        .map(|x| x.join("flake.lock"));
+    let newest = [config, lock]
+        .iter()
+        .flatten()
+        .filter_map(modified)
+        .max()
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    match modified(&path) {
+        Some(t) if t >= newest => {
+            let content = std::fs::read_to_string(&path)?;
+            serde_json::from_str(&content).context("failed to parse option index")
+        }
+        _ => {
+            eprintln!(
+                "Indexing {} options...",
+                if home { "Home Manager" } else { "NixOS" }
+            );
+            update_index(home)
+        }
+    }
+}
+
+pub fn search(query: &[&str], home: bool, json: bool) -> Result<()> {
+    let terms = query.iter().map(|x| x.to_lowercase()).collect::<Vec<_>>();
+    let mut results = index(home)?
+        .into_iter()
+        .filter_map(|o| {
+            let name = o.name.to_lowercase();
+            let description = o.description.as_deref().unwrap_or_default().to_lowercase();
+            let mut score = 0;
+            for t in &terms {
+                if name.split('.').any(|x| x == t) {
+                    score += 3;
+                } else if name.contains(t.as_str()) {
+                    score += 2;
+                } else if description.contains(t.as_str()) {
+                    score += 1;
+                } else {
+                    return None;
+                }
+            }
+            Some((score, o))
+        })
+        .collect::<Vec<_>>();
+    results.sort_by(|(a, x), (b, y)| {
+        b.cmp(a)
+            .then(x.name.len().cmp(&y.name.len()))
+            .then(x.name.cmp(&y.name))
+    });
+    results.truncate(100);
+
+    if json {
+        let results = results.into_iter().map(|(_, o)| o).collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+
+    for (_, o) in results.into_iter().rev() {
+        let name = o
+            .name
+            .if_supports_color(Stdout, |t| highlight(&format!("{}", t.bold()), query))
+            .to_string();
+        println!(
+            "* {} ({})",
+            name,
+            o.option_type
+                .if_supports_color(Stdout, |t| t.style(*crate::VERSIONSTYLE))
+        );
+        if let Some(description) = o.description {
+            println!(
+                "  {}",
+                description
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .if_supports_color(Stdout, |t| highlight(t, query))
+            );
+        }
+        println!();
+    }
+    Ok(())
+}
+
+pub fn show(option: &str, home: bool, json: bool) -> Result<()> {
+    let o = index(home)?
+        .into_iter()
+        .find(|o| o.name == option)
+        .ok_or_else(|| anyhow!("option {} does not exist", option))?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&o)?);
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        o.name.if_supports_color(Stdout, |t| t.style(*PKGSTYLE))
+    );
+    println!("  Type: {}", o.option_type);
+    if let Some(default) = o.default {
+        println!("  Default: {}", default.trim());
+    }
+    if let Some(example) = o.example {
+        println!("  Example: {}", example.trim());
+    }
+    for file in o.declarations {
+        println!("  Declared in: {}", file);
+    }
+    if let Some(description) = o.description {
+        println!();
+        for line in description.trim().lines() {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
+}
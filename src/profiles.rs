@@ -0,0 +1,293 @@
+use crate::{
+    PKGSTYLE,
+    scope::attr_name,
+    shell::installable,
+    store::{split_name, store_name},
+};
+use anyhow::{Context, Result, anyhow};
+use libsnow::{Package, PackageAttr, metadata::Metadata};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+};
+
+static SELECTED: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+fn home() -> String {
+    std::env::var("HOME").unwrap_or_default()
+}
+
+/// Directory holding the profiles of the user
+fn state_dir() -> PathBuf {
+    match std::env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(format!("{}/.local/state", home())),
+    }
+    .join("nix")
+    .join("profiles")
+}
+
+/// Directory named profiles are kept in
+pub fn dir() -> PathBuf {
+    state_dir().join("snow")
+}
+
+/// File recording the profile `~/.nix-profile` pointed to before a named profile was first
+/// activated
+fn default_record() -> PathBuf {
+    dir().join(".default")
+}
+
+/// Profile `~/.nix-profile` points to when no named profile is active. This is the one it
+/// pointed to before the first switch, e.g. `/nix/var/nix/profiles/per-user/$USER/profile`
+/// on older installations.
+fn default_profile() -> PathBuf {
+    std::fs::read_to_string(default_record())
+        .ok()
+        .map(|x| PathBuf::from(x.trim()))
+        .filter(|x| !x.as_os_str().is_empty())
+        .or_else(|| active().filter(|x| !x.starts_with(dir())))
+        .unwrap_or_else(|| state_dir().join("profile"))
+}
+
+/// Path of the named profile `name`
+pub fn path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        return Err(anyhow!("invalid profile name {}", name));
+    }
+    Ok(dir().join(name))
+}
+
+/// Select the profile given with `--profile` or `--profile-path`
+pub fn select(name: Option<String>, path: Option<PathBuf>) -> Result<()> {
+    let selected = match (name, path) {
+        (Some(name), _) => Some(self::path(&name)?),
+        (None, Some(path)) => Some(path),
+        (None, None) => None,
+    };
+    let _ = SELECTED.set(selected);
+    Ok(())
+}
+
+/// Profile selected with `--profile` or `--profile-path`, if any
+pub fn selected() -> Option<PathBuf> {
+    SELECTED.get().cloned().flatten()
+}
+
+/// Profile the profile scope acts on
+pub fn root() -> PathBuf {
+    selected().unwrap_or_else(|| PathBuf::from(format!("{}/.nix-profile", home())))
+}
+
+fn nix_profile(command: &str, profile: &Path, args: &[String]) -> Result<()> {
+    let status = Command::new("nix")
+        .args(["profile", command, "--profile"])
+        .arg(profile)
+        .args(args)
+        .status()
+        .context("failed to run nix profile")?;
+    if !status.success() {
+        return Err(anyhow!("nix profile {} failed", command));
+    }
+    Ok(())
+}
+
+/// Install `packages` into `profile`, attributes are taken from nixpkgs
+pub fn install(profile: &Path, packages: &[&str], md: &Metadata) -> Result<()> {
+    let installables = packages
+        .iter()
+        .map(|x| installable(x, md))
+        .collect::<Result<Vec<_>>>()?;
+    nix_profile("install", profile, &installables)
+}
+
+pub fn remove(profile: &Path, packages: &[&str]) -> Result<()> {
+    nix_profile(
+        "remove",
+        profile,
+        &packages.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+    )
+}
+
+/// Upgrade `packages` in `profile`, or every package if none are given
+pub fn update(profile: &Path, packages: &[&str]) -> Result<()> {
+    if packages.is_empty() {
+        return nix_profile("upgrade", profile, &["--all".to_string()]);
+    }
+    nix_profile(
+        "upgrade",
+        profile,
+        &packages.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+    )
+}
+
+/// Element of a profile manifest
+pub struct Element {
+    /// nixpkgs attribute the element was installed from
+    pub attr: Option<String>,
+    /// Name `nix profile` knows the element by
+    pub name: Option<String>,
+    pub store_path: Option<String>,
+}
+
+/// Elements of `profile`, read from its manifest
+pub fn elements(profile: &Path) -> Result<Vec<Element>> {
+    let manifest = profile.join("manifest.json");
+    let content = std::fs::read_to_string(&manifest)
+        .with_context(|| format!("failed to read {}", manifest.display()))?;
+    let manifest: serde_json::Value = serde_json::from_str(&content)?;
+    // Version 3 manifests key elements by name, older ones store a list
+    let elements: Vec<(Option<&String>, &serde_json::Value)> = match &manifest["elements"] {
+        serde_json::Value::Object(map) => map.iter().map(|(k, v)| (Some(k), v)).collect(),
+        serde_json::Value::Array(list) => list.iter().map(|v| (None, v)).collect(),
+        _ => vec![],
+    };
+    Ok(elements
+        .into_iter()
+        .map(|(name, element)| Element {
+            attr: element["attrPath"]
+                .as_str()
+                .map(|x| attr_name(&format!("#{}", x))),
+            name: name.cloned(),
+            store_path: element["storePaths"]
+                .get(0)
+                .and_then(|x| x.as_str())
+                .map(str::to_string),
+        })
+        .collect())
+}
+
+/// Packages of `profile`, read from its manifest
+pub fn list(profile: &Path) -> Result<Vec<Package>> {
+    Ok(elements(profile)?
+        .into_iter()
+        .filter_map(|element| {
+            let (pname, version) = element
+                .store_path
+                .map(|x| split_name(&store_name(Path::new(&x))))
+                .unwrap_or_default();
+            let attr = element
+                .attr
+                .or(element.name)
+                .or(Some(pname).filter(|x| !x.is_empty()))?;
+            Some(Package {
+                attr: PackageAttr::NixPkgs { attr },
+                version,
+            })
+        })
+        .collect())
+}
+
+/// Profile `~/.nix-profile` points to
+fn active() -> Option<PathBuf> {
+    std::fs::read_link(format!("{}/.nix-profile", home()))
+        .ok()
+        .map(|x| Path::new(&home()).join(x))
+}
+
+/// Create the named profile `name` with `packages`, nix only creates a profile when installing
+pub async fn create(name: &str, packages: &[&str]) -> Result<()> {
+    if name == "default" {
+        return Err(anyhow!(
+            "default is the profile ~/.nix-profile pointed to before switching, pick another name"
+        ));
+    }
+    let path = path(name)?;
+    if path.exists() {
+        return Err(anyhow!("profile {} already exists", name));
+    }
+    let md = Metadata::connect().await?;
+    std::fs::create_dir_all(dir())?;
+    install(&path, packages, &md)?;
+    println!("Created profile {}", name);
+    Ok(())
+}
+
+/// Print the named profiles, marking the active one
+pub fn print() -> Result<()> {
+    let active = active();
+    let mark = |path: &Path, name: &str| {
+        if active.as_deref() == Some(path) {
+            format!(
+                "{} (active)",
+                name.if_supports_color(Stdout, |t| t.style(*PKGSTYLE))
+            )
+        } else {
+            name.to_string()
+        }
+    };
+    println!("{}", mark(&default_profile(), "default"));
+    let mut names = std::fs::read_dir(dir())
+        .into_iter()
+        .flat_map(|x| x.flatten())
+        .map(|x| x.file_name().to_string_lossy().to_string())
+        // Generations of a profile are kept next to it as `<name>-<n>-link`
+        .filter(|x| !x.ends_with("-link") && !x.starts_with('.'))
+        .collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        println!("{}", mark(&dir().join(&name), &name));
+    }
+    Ok(())
+}
+
+/// Delete the named profile `name` and its generations
+pub fn delete(name: &str) -> Result<()> {
+    let path = path(name)?;
+    if !path.is_symlink() {
+        return Err(anyhow!("profile {} does not exist", name));
+    }
+    if active().as_deref() == Some(path.as_path()) {
+        return Err(anyhow!(
+            "profile {} is active, activate another one first",
+            name
+        ));
+    }
+    let prefix = format!("{}-", name);
+    for entry in std::fs::read_dir(dir())?.flatten() {
+        let file = entry.file_name().to_string_lossy().to_string();
+        if file
+            .strip_prefix(&prefix)
+            .and_then(|x| x.strip_suffix("-link"))
+            .is_some_and(|x| x.chars().all(|c| c.is_ascii_digit()))
+        {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    std::fs::remove_file(&path)?;
+    println!(
+        "Deleted profile {}, run `nix-collect-garbage` to free its space",
+        name
+    );
+    Ok(())
+}
+
+/// Point `~/.nix-profile` to the named profile `name`, `default` restores the default profile
+pub fn activate(name: &str) -> Result<()> {
+    let target = if name == "default" {
+        default_profile()
+    } else {
+        path(name)?
+    };
+    if !target.exists() {
+        return Err(anyhow!("profile {} does not exist", name));
+    }
+    // Remember the profile in use before the first switch, it is the default one
+    if !default_record().exists() {
+        std::fs::create_dir_all(dir())?;
+        std::fs::write(
+            default_record(),
+            default_profile().to_string_lossy().as_bytes(),
+        )?;
+    }
+    let link = PathBuf::from(format!("{}/.nix-profile", home()));
+    // Replace the link atomically so a shell started meanwhile never misses it
+    let tmp = link.with_file_name(".nix-profile.snow");
+    let _ = std::fs::remove_file(&tmp);
+    std::os::unix::fs::symlink(&target, &tmp)?;
+    std::fs::rename(&tmp, &link).with_context(|| format!("failed to update {}", link.display()))?;
+    println!("Activated profile {}", name);
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use std::io::Write;
+
+/// Ask `question` on the terminal and return the trimmed answer
+pub fn ask(question: &str) -> String {
+    print!("{} ", question);
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    let _ = std::io::stdin().read_line(&mut answer);
+    answer.trim().to_string()
+}
+
+/// Ask a yes/no question, defaulting to no
+pub fn confirm(question: &str) -> bool {
+    matches!(
+        ask(&format!("{} [y/N]", question)).to_lowercase().as_str(),
+        "y" | "yes"
+    )
+}
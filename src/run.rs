@@ -0,0 +1,69 @@
+use crate::{PKGSTYLE, binaries::binaries, prompt, scope::Scope, shell::installable, store};
+use anyhow::{Context, Result, anyhow};
+use libsnow::metadata::Metadata;
+use owo_colors::{OwoColorize, Stream::Stdout};
+use std::process::{Command, Stdio};
+
+/// Print the executables provided by `package`, building it if needed
+pub async fn list_bins(package: &str) -> Result<()> {
+    let md = Metadata::connect().await?;
+    let paths = store::build(&installable(package, &md)?)?;
+    let bins = binaries(&paths);
+    if bins.is_empty() {
+        return Err(anyhow!("{} does not provide any executables", package));
+    }
+    for bin in bins {
+        println!("{}", bin);
+    }
+    Ok(())
+}
+
+/// Run `package`, using the executable `bin` instead of its main program when given.
+/// Picking `bin` resolves the package the way `snow shell` does. Returns the exit code of the program.
+pub async fn run(package: &str, bin: Option<&str>, arguments: &[&str]) -> Result<i32> {
+    let Some(bin) = bin else {
+        libsnow::profile::run::run(package, arguments).await?;
+        return Ok(0);
+    };
+    let md = Metadata::connect().await?;
+    let status = Command::new("nix")
+        .args(["shell", &installable(package, &md)?, "--command", bin])
+        .args(arguments)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("failed to run nix")?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Install `package` permanently, asking for the scope when none is given
+pub async fn keep(package: &str, scope: Option<Scope>) -> Result<()> {
+    let scope = match scope {
+        Some(s) => s,
+        None => {
+            let configured = Scope::ALL
+                .into_iter()
+                .filter(Scope::is_configured)
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>();
+            let answer = prompt::ask(&format!(
+                "Install {} permanently? [{}/N]",
+                package.if_supports_color(Stdout, |t| t.style(*PKGSTYLE)),
+                configured.join("/")
+            ));
+            match Scope::ALL
+                .into_iter()
+                .find(|x| x.to_string() == answer.to_lowercase())
+            {
+                Some(s) => s,
+                None => return Ok(()),
+            }
+        }
+    };
+    if !scope.is_configured() {
+        return Err(anyhow!("{} scope is not configured", scope));
+    }
+    let md = Metadata::connect().await?;
+    crate::scope::install(scope, &[package], &md).await
+}
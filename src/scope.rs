@@ -0,0 +1,254 @@
+use crate::{
+    config, edit, files, home_manager_installed, homemodule, host, is_home_configured,
+    is_profile_configured, is_system_configured, legacy, profiles, search,
+};
+use anyhow::{Result, anyhow};
+use libsnow::{Package, metadata::Metadata, nixos::AuthMethod};
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::PathBuf};
+
+/// Place a package can be installed to
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Profile,
+    System,
+    Home,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Profile => write!(f, "profile"),
+            Scope::System => write!(f, "system"),
+            Scope::Home => write!(f, "home"),
+        }
+    }
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::Profile, Scope::System, Scope::Home];
+
+    /// Heading used when listing the packages of the scope
+    pub fn title(&self) -> &'static str {
+        match self {
+            Scope::Profile => "Profile Packages:",
+            Scope::System => "System Packages:",
+            Scope::Home => "Home Manager Packages:",
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        match self {
+            Scope::Profile => match profiles::selected() {
+                Some(path) => path.exists(),
+                None => is_profile_configured() || legacy::detected(),
+            },
+            Scope::System => is_system_configured(),
+            Scope::Home => {
+                homemodule::detected() || home_manager_installed() && is_home_configured()
+            }
+        }
+    }
+}
+
+/// Name of the configuration of `scope` selected with `--host` or `--home-config`, if it is not
+/// the one of this machine and user
+fn other_config(scope: Scope) -> Option<String> {
+    match scope {
+        Scope::Profile => None,
+        Scope::System => host::other(false),
+        Scope::Home => host::other(true),
+    }
+}
+
+pub async fn install(scope: Scope, packages: &[&str], md: &Metadata) -> Result<()> {
+    if let Some(name) = other_config(scope) {
+        host::install(&name, scope == Scope::Home, packages, md)?;
+        return host::rebuild(&name, scope == Scope::Home);
+    }
+    if scope == Scope::Profile
+        && let Some(path) = profiles::selected()
+    {
+        return profiles::install(&path, packages, md);
+    }
+    if scope == Scope::Home && homemodule::detected() {
+        return homemodule::install(packages, md).await;
+    }
+    match scope {
+        Scope::Profile if legacy::detected() => legacy::install(packages),
+        Scope::Profile => libsnow::profile::install::install(packages).await,
+        Scope::System => libsnow::nixos::install::install(packages, md, AuthMethod::Sudo).await,
+        Scope::Home => libsnow::homemanager::install::install(packages, md, AuthMethod::Sudo).await,
+    }
+}
+
+/// Upgrade `packages` of the profile, or all of its packages if none are given
+pub async fn update_profile(packages: &[&str]) -> Result<()> {
+    if let Some(path) = profiles::selected() {
+        return profiles::update(&path, packages);
+    }
+    if legacy::detected() {
+        return legacy::update(packages);
+    }
+    if packages.is_empty() {
+        libsnow::profile::update::update_all().await
+    } else {
+        libsnow::profile::update::update(packages).await
+    }
+}
+
+/// File and option of the package list of `scope`, for the scopes declared in a configuration
+fn package_list(scope: Scope) -> Result<Option<(PathBuf, String)>> {
+    let home = scope == Scope::Home;
+    let option = if home {
+        "home.packages"
+    } else {
+        "environment.systemPackages"
+    };
+    if scope == Scope::Profile {
+        return Ok(None);
+    }
+    if let Some(name) = other_config(scope) {
+        return Ok(Some((host::package_file(&name, home)?, option.to_string())));
+    }
+    if home && homemodule::detected() {
+        let declaration = homemodule::declaration()?;
+        return Ok(Some((declaration.file.clone(), declaration.option(option))));
+    }
+    let file = if home {
+        config::home_config_file()
+    } else {
+        config::system_config_file()
+    }
+    .ok_or_else(|| anyhow!("configuration file is not set, run `snow init`"))?;
+    Ok(Some((file, option.to_string())))
+}
+
+/// Install `add` and remove `remove` in `scope`. Configurations are edited first and rebuilt
+/// once for both.
+pub async fn change(scope: Scope, add: &[&str], remove: &[&str], md: &Metadata) -> Result<()> {
+    if let Some((file, option)) = package_list(scope)? {
+        search::validate(md, add)?;
+        if !add.is_empty() {
+            edit::add_packages(&file, &option, add)?;
+        }
+        if !remove.is_empty() {
+            edit::remove_packages(&file, &option, remove)?;
+        }
+        return files::rebuild(scope).await;
+    }
+    if !add.is_empty() {
+        install(scope, add, md).await?;
+    }
+    if !remove.is_empty() {
+        self::remove(scope, remove, md).await?;
+    }
+    Ok(())
+}
+
+pub async fn remove(scope: Scope, packages: &[&str], md: &Metadata) -> Result<()> {
+    if let Some(name) = other_config(scope) {
+        host::remove(&name, scope == Scope::Home, packages)?;
+        return host::rebuild(&name, scope == Scope::Home);
+    }
+    if scope == Scope::Profile
+        && let Some(path) = profiles::selected()
+    {
+        return profiles::remove(&path, packages);
+    }
+    if scope == Scope::Home && homemodule::detected() {
+        return homemodule::remove(packages).await;
+    }
+    match scope {
+        Scope::Profile if legacy::detected() => legacy::remove(packages),
+        Scope::Profile => libsnow::profile::remove::remove(packages).await,
+        Scope::System => libsnow::nixos::remove::remove(packages, md, AuthMethod::Sudo).await,
+        Scope::Home => libsnow::homemanager::remove::remove(packages, md, AuthMethod::Sudo).await,
+    }
+}
+
+/// Packages of the profile, unless it is a nix-env profile whose packages need the metadata
+/// to map them to attributes
+pub fn list_profile() -> Option<Result<Vec<Package>>> {
+    match profiles::selected() {
+        Some(path) => Some(profiles::list(&path)),
+        None if legacy::detected() => None,
+        None => Some(libsnow::profile::list::list()),
+    }
+}
+
+pub fn list(scope: Scope, md: &Metadata) -> Result<Vec<Package>> {
+    if let Some(name) = other_config(scope) {
+        return host::list(&name, scope == Scope::Home, md);
+    }
+    if scope == Scope::Profile
+        && let Some(res) = list_profile()
+    {
+        return res;
+    }
+    if scope == Scope::Home && homemodule::detected() {
+        return homemodule::list(md);
+    }
+    match scope {
+        Scope::Profile => legacy::list(md),
+        Scope::System => libsnow::nixos::list::list_systempackages(md),
+        Scope::Home => libsnow::homemanager::list::list(md),
+    }
+}
+
+/// Attribute path of a package without the flake reference it was installed from.
+/// Profile elements installed from a flake look like `github:NixOS/nixpkgs#legacyPackages.x86_64-linux.hello`.
+pub fn attr_name(element: &str) -> String {
+    match element.split_once('#') {
+        Some((_, path)) => {
+            let parts = path.split('.').collect::<Vec<_>>();
+            match parts.as_slice() {
+                ["legacyPackages" | "packages", _, rest @ ..] if !rest.is_empty() => rest.join("."),
+                _ => path.to_string(),
+            }
+        }
+        None => element.to_string(),
+    }
+}
+
+/// Install `packages` into `to`, then remove them from `from` once the install succeeded
+pub async fn move_packages(packages: &[&str], from: Scope, to: Scope) -> Result<()> {
+    if from == to {
+        return Err(anyhow!("source and target scope are both {}", from));
+    }
+    for scope in [from, to] {
+        if !scope.is_configured() {
+            return Err(anyhow!("{} scope is not configured", scope));
+        }
+    }
+
+    let md = Metadata::connect().await?;
+    let installed = list(from, &md)?
+        .into_iter()
+        .map(|x| x.attr.to_string())
+        .collect::<Vec<_>>();
+    if let Some(missing) = packages.iter().find(|x| !installed.iter().any(|y| y == *x)) {
+        return Err(anyhow!(
+            "{} is not installed in the {} scope",
+            missing,
+            from
+        ));
+    }
+
+    install(to, packages, &md).await.map_err(|e| {
+        anyhow!(
+            "failed to install into {} scope, nothing was removed: {}",
+            to,
+            e
+        )
+    })?;
+    remove(from, packages, &md).await.map_err(|e| {
+        anyhow!(
+            "installed into {} scope but failed to remove from {} scope: {}",
+            to,
+            from,
+            e
+        )
+    })
+}
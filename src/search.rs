@@ -1,13 +1,55 @@
-use crate::{VERSIONSTYLE, is_home_configured, is_profile_configured, is_system_configured};
-use anyhow::Result;
-use libsnow::metadata::SearchResult;
+use crate::{VERSIONSTYLE, is_system_configured, scope::Scope};
+use anyhow::{Result, anyhow};
+use libsnow::metadata::{Metadata, SearchResult};
 use owo_colors::{OwoColorize, Stream::Stdout};
 
-pub async fn search(query: &[&str]) -> Result<()> {
+/// Highlight every case-insensitive occurrence of the query terms in `text`
+pub fn highlight(text: &str, query: &[&str]) -> String {
+    let mut t = text.to_string();
+    for q in query {
+        let qlower = q.to_lowercase();
+        let tlower = t.to_lowercase();
+        if tlower.contains(&qlower) {
+            let m = tlower.match_indices(&qlower);
+            let mut off = 0;
+            for (i, s) in m {
+                t.replace_range(
+                    i + off..i + off + s.len(),
+                    t[i + off..i + off + s.len()]
+                        .to_string()
+                        .bright_green()
+                        .to_string()
+                        .as_str(),
+                );
+                off += 10;
+            }
+        }
+    }
+    t
+}
+
+/// Find the metadata entry whose attribute is exactly `attr`
+pub fn lookup(md: &Metadata, attr: &str) -> Option<SearchResult> {
+    md.get(attr).ok().flatten()
+}
+
+/// Check that every attribute of `packages` is in nixpkgs, before a configuration is edited
+pub fn validate(md: &Metadata, packages: &[&str]) -> Result<()> {
+    match packages.iter().find(|x| lookup(md, x).is_none()) {
+        Some(x) => Err(anyhow!(
+            "{} was not found in nixpkgs, try `snow search {}`",
+            x,
+            x
+        )),
+        None => Ok(()),
+    }
+}
+
+pub async fn search(query: &[&str], json: bool) -> Result<()> {
     let md = libsnow::metadata::Metadata::connect().await?;
 
-    let currprofilepkgs = if is_profile_configured() {
-        libsnow::profile::list::list()
+    let currprofilepkgs = if Scope::Profile.is_configured() {
+        crate::scope::list(Scope::Profile, &md)
             .unwrap_or_default()
             .into_iter()
             .map(|x| x.attr.to_string())
@@ -24,8 +66,8 @@ pub async fn search(query: &[&str]) -> Result<()> {
     } else {
         vec![]
     };
-    let currhomepkgs = if is_home_configured() {
-        libsnow::homemanager::list::list(&md)
+    let currhomepkgs = if Scope::Home.is_configured() {
+        crate::scope::list(Scope::Home, &md)
             .unwrap_or_default()
             .into_iter()
             .map(|x| x.attr.to_string())
@@ -34,7 +76,64 @@ pub async fn search(query: &[&str]) -> Result<()> {
         vec![]
     };
 
+    let curruserpkgs = if is_system_configured() {
+        crate::users::list(&crate::users::name(None), &md)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|x| x.attr.to_string())
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+
+    let currfonts = crate::fonts::list(&md)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|x| x.attr.to_string())
+        .collect::<Vec<_>>();
+
     let mut search_result = md.search(&query.join(" "), 100, 0.0)?;
+    // Marking fonts is a hint, a search still works when nixpkgs cannot be evaluated
+    let fonts = crate::fonts::fonts_of(
+        &search_result
+            .iter()
+            .map(|x| x.attribute.clone())
+            .collect::<Vec<_>>(),
+    )
+    .unwrap_or_default();
+
+    if json {
+        let results = search_result
+            .into_iter()
+            .map(|x| {
+                let installed = [
+                    ("profile", &currprofilepkgs),
+                    ("system", &currsyspkgs),
+                    ("home", &currhomepkgs),
+                    ("user-packages", &curruserpkgs),
+                    ("fonts", &currfonts),
+                ]
+                .into_iter()
+                .filter(|(_, pkgs)| pkgs.contains(&x.attribute))
+                .map(|(scope, _)| scope)
+                .collect::<Vec<_>>();
+                serde_json::json!({
+                    "attribute": x.attribute,
+                    "pname": x.pname,
+                    "version": x.version,
+                    "description": x.description,
+                    "broken": x.broken,
+                    "insecure": x.insecure,
+                    "unfree": x.unfree,
+                    "font": fonts.contains(&x.attribute) || currfonts.contains(&x.attribute),
+                    "installed": installed,
+                })
+            })
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+
     search_result.reverse();
 
     for SearchResult {
@@ -49,29 +148,7 @@ pub async fn search(query: &[&str]) -> Result<()> {
     } in search_result
     {
         let mut pkg = attribute
-            .if_supports_color(Stdout, |t| {
-                let mut t = format!("{}", t.bold());
-                for q in query {
-                    let qlower = q.to_lowercase();
-                    let tlower = t.to_lowercase();
-                    if tlower.contains(&qlower) {
-                        let m = tlower.match_indices(&qlower);
-                        let mut off = 0;
-                        for (i, s) in m {
-                            t.replace_range(
-                                i + off..i + off + s.len(),
-                                t[i + off..i + off + s.len()]
-                                    .to_string()
-                                    .bright_green()
-                                    .to_string()
-                                    .as_str(),
-                            );
-                            off += 10;
-                        }
-                    }
-                }
-                t
-            })
+            .if_supports_color(Stdout, |t| highlight(&format!("{}", t.bold()), query))
             .to_string();
         if currprofilepkgs.contains(&attribute) {
             pkg = format!("{} ({})", pkg, "user".bright_cyan());
@@ -82,6 +159,23 @@ pub async fn search(query: &[&str]) -> Result<()> {
         if currhomepkgs.contains(&attribute) {
             pkg = format!("{} ({})", pkg, "home".bright_yellow());
         }
+        if curruserpkgs.contains(&attribute) {
+            pkg = format!("{} ({})", pkg, "user-packages".bright_blue());
+        }
+        if currfonts.contains(&attribute) {
+            pkg = format!(
+                "{} ({})",
+                pkg,
+                "font, installed"
+                    .if_supports_color(Stdout, |t| t.bright_green().bold().to_string())
+            );
+        } else if fonts.contains(&attribute) {
+            pkg = format!(
+                "{} ({})",
+                pkg,
+                "font".if_supports_color(Stdout, |t| t.bright_green())
+            );
+        }
         if let Some(version) = version {
             pkg = format!(
                 "{} ({})",
@@ -114,29 +208,9 @@ pub async fn search(query: &[&str]) -> Result<()> {
             println!(
                 "* {}\n  {}\n",
                 pkg,
-                description.as_str().if_supports_color(Stdout, |t| {
-                    let mut t = t.to_string();
-                    for q in query {
-                        let qlower = q.to_lowercase();
-                        let tlower = t.to_lowercase();
-                        if tlower.contains(&qlower) {
-                            let m = tlower.match_indices(&qlower);
-                            let mut off = 0;
-                            for (i, s) in m {
-                                t.replace_range(
-                                    i + off..i + off + s.len(),
-                                    t[i + off..i + off + s.len()]
-                                        .to_string()
-                                        .bright_green()
-                                        .to_string()
-                                        .as_str(),
-                                );
-                                off += 10;
-                            }
-                        }
-                    }
-                    t
-                })
+                description
+                    .as_str()
+                    .if_supports_color(Stdout, |t| highlight(t, query))
             );
         } else {
             println!("* {}\n", pkg);
@@ -0,0 +1,43 @@
+use crate::search::lookup;
+use anyhow::{Context, Result, anyhow};
+use libsnow::metadata::Metadata;
+use std::process::{Command, Stdio};
+
+/// Turn a package argument into a flake installable, checking plain attributes against the metadata.
+/// Arguments that already are flake references (`github:owner/repo#pkg`, `nixpkgs#hello`) are used as is.
+pub fn installable(package: &str, md: &Metadata) -> Result<String> {
+    if package.contains('#') || package.contains(':') {
+        return Ok(package.to_string());
+    }
+    if lookup(md, package).is_none() {
+        return Err(anyhow!(
+            "{} was not found in nixpkgs, try `snow search {}`",
+            package,
+            package
+        ));
+    }
+    Ok(format!("nixpkgs#{}", package))
+}
+
+/// Start a shell with `packages` on PATH, or run `command` inside it.
+/// Returns the exit code of the shell or command.
+pub async fn shell(packages: &[&str], command: &[&str]) -> Result<i32> {
+    let md = Metadata::connect().await?;
+    let installables = packages
+        .iter()
+        .map(|x| installable(x, &md))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut cmd = Command::new("nix");
+    cmd.arg("shell").args(&installables);
+    if !command.is_empty() {
+        cmd.arg("--command").args(command);
+    }
+    let status = cmd
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("failed to run nix shell")?;
+    Ok(status.code().unwrap_or(1))
+}
@@ -0,0 +1,170 @@
+use crate::{
+    PKGSTYLE, VERSIONSTYLE,
+    scope::{Scope, attr_name},
+    store,
+};
+use anyhow::{Result, anyhow};
+use libsnow::metadata::Metadata;
+use owo_colors::{OwoColorize, Stream::Stdout};
+use std::{collections::HashMap, path::PathBuf};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizeSort {
+    Name,
+    #[default]
+    Size,
+    Unique,
+}
+
+#[derive(Debug, Clone)]
+pub struct PackageSize {
+    pub attr: String,
+    pub version: Option<String>,
+    /// Size of the package's whole closure
+    pub closure: u64,
+    /// Size of the part of the closure no other package of the scope depends on
+    pub unique: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScopeSize {
+    pub scope: Scope,
+    pub packages: Vec<PackageSize>,
+    /// Size of the combined closure of all packages
+    pub total: u64,
+}
+
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Compute the closure size and unique contribution of every package in `scope`
+pub fn sizes(scope: Scope, md: &Metadata) -> Result<ScopeSize> {
+    let root = store::generation_root(scope)
+        .ok_or_else(|| anyhow!("no current {} generation found", scope))?;
+    let nar_sizes = store::nar_sizes(&root)?;
+    let installed = store::installed(scope, md)?;
+    let closures = installed
+        .iter()
+        .map(|x| store::requisites(&x.paths))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut counts: HashMap<&PathBuf, usize> = HashMap::new();
+    for closure in &closures {
+        for path in closure {
+            *counts.entry(path).or_default() += 1;
+        }
+    }
+    let size = |path: &PathBuf| nar_sizes.get(path).copied().unwrap_or_default();
+
+    let packages = installed
+        .iter()
+        .zip(&closures)
+        .map(|(pkg, closure)| PackageSize {
+            attr: pkg.attr.clone(),
+            version: pkg.version.clone(),
+            closure: closure.iter().map(size).sum(),
+            unique: closure
+                .iter()
+                .filter(|x| counts.get(x) == Some(&1))
+                .map(size)
+                .sum(),
+        })
+        .collect();
+    Ok(ScopeSize {
+        scope,
+        packages,
+        total: counts.keys().map(|x| size(x)).sum(),
+    })
+}
+
+pub fn print(sizes: &ScopeSize, sort: SizeSort) {
+    print_packages(sizes.scope, &sizes.packages, sort);
+    println!("Total: {}", format_size(sizes.total));
+}
+
+fn print_packages(scope: Scope, packages: &[PackageSize], sort: SizeSort) {
+    let mut packages = packages.to_vec();
+    match sort {
+        SizeSort::Name => packages.sort_by(|a, b| a.attr.cmp(&b.attr)),
+        SizeSort::Size => packages.sort_by_key(|p| std::cmp::Reverse(p.closure)),
+        SizeSort::Unique => packages.sort_by_key(|p| std::cmp::Reverse(p.unique)),
+    }
+    println!(
+        "{}",
+        scope.title().if_supports_color(Stdout, |t| t.bright_cyan())
+    );
+
+    let names = packages
+        .iter()
+        .map(|x| match &x.version {
+            Some(v) => format!("{} ({})", x.attr, v),
+            None => x.attr.clone(),
+        })
+        .collect::<Vec<_>>();
+    let width = names.iter().map(|x| x.chars().count()).max().unwrap_or(0);
+    println!("{:width$}  {:>10}  {:>10}", "", "Closure", "Unique");
+    for (pkg, name) in packages.iter().zip(names) {
+        let padding = " ".repeat(width - name.chars().count());
+        let name = match &pkg.version {
+            Some(v) => format!(
+                "{} ({})",
+                pkg.attr.if_supports_color(Stdout, |t| t.style(*PKGSTYLE)),
+                v.if_supports_color(Stdout, |t| t.style(*VERSIONSTYLE))
+            ),
+            None => pkg
+                .attr
+                .if_supports_color(Stdout, |t| t.style(*PKGSTYLE))
+                .to_string(),
+        };
+        println!(
+            "{}{}  {:>10}  {:>10}",
+            name,
+            padding,
+            format_size(pkg.closure),
+            format_size(pkg.unique)
+        );
+    }
+}
+
+/// Print the sizes of `attrs` in every configured scope they are installed in
+pub async fn size(attrs: &[&str], sort: SizeSort) -> Result<()> {
+    let md = Metadata::connect().await?;
+    let mut found = vec![];
+    let mut printed = false;
+    for scope in Scope::ALL.into_iter().filter(Scope::is_configured) {
+        let packages = sizes(scope, &md)?
+            .packages
+            .into_iter()
+            .filter(|x| {
+                attrs
+                    .iter()
+                    .any(|a| attr_name(&x.attr) == *a || x.attr == *a)
+            })
+            .collect::<Vec<_>>();
+        if packages.is_empty() {
+            continue;
+        }
+        found.extend(packages.iter().map(|x| attr_name(&x.attr)));
+        if printed {
+            println!();
+        }
+        print_packages(scope, &packages, sort);
+        printed = true;
+    }
+    if let Some(missing) = attrs.iter().find(|a| !found.iter().any(|x| x == *a)) {
+        return Err(anyhow!("{} is not installed", missing));
+    }
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use crate::{
+    flake,
+    scope::{self, Scope, attr_name},
+};
+use anyhow::{Context, Result, anyhow};
+use libsnow::metadata::Metadata;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+const OUTPUTS: [&str; 9] = [
+    "bin", "dev", "doc", "info", "lib", "man", "out", "devdoc", "debug",
+];
+
+/// Directory that holds the packages of the current generation of `scope`
+pub fn generation_root(scope: Scope) -> Option<PathBuf> {
+    let home = std::env::var("HOME").ok()?;
+    let root = match scope {
+        Scope::Profile => crate::profiles::root(),
+        Scope::System => PathBuf::from("/run/current-system/sw"),
+        Scope::Home => {
+            let standalone =
+                PathBuf::from(format!("{}/.local/state/nix/profiles/home-manager", home));
+            if standalone.exists() {
+                standalone.join("home-path")
+            } else {
+                PathBuf::from(format!(
+                    "/etc/profiles/per-user/{}",
+                    std::env::var("USER").ok()?
+                ))
+            }
+        }
+    };
+    root.exists().then_some(root)
+}
+
+/// Store paths directly referenced by `path`
+pub fn references(path: &Path) -> Result<Vec<PathBuf>> {
+    let output = Command::new("nix-store")
+        .args(["--query", "--references"])
+        .arg(path)
+        .output()
+        .context("failed to run nix-store")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "failed to query references of {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Name of a store path without its hash and output suffix, e.g. `hello-2.12.1`
+pub fn store_name(path: &Path) -> String {
+    let base = path
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match base.split_once('-') {
+        Some((hash, name)) if hash.len() == 32 => name.to_string(),
+        _ => base,
+    };
+    match name.rsplit_once('-') {
+        Some((rest, output)) if OUTPUTS.contains(&output) => rest.to_string(),
+        _ => name,
+    }
+}
+
+/// Split a derivation name into name and version the way Nix does:
+/// the version starts at the first dash that is not followed by a letter
+pub fn split_name(name: &str) -> (String, Option<String>) {
+    let bytes = name.as_bytes();
+    for (i, c) in bytes.iter().enumerate() {
+        if *c == b'-' && bytes.get(i + 1).is_some_and(|x| !x.is_ascii_alphabetic()) {
+            return (name[..i].to_string(), Some(name[i + 1..].to_string()));
+        }
+    }
+    (name.to_string(), None)
+}
+
+/// Top level store path containing `path`, e.g. `/nix/store/<hash>-hello-2.12.1` for its `bin/hello`
+pub fn store_path(path: &Path) -> Option<PathBuf> {
+    let rest = path.strip_prefix("/nix/store").ok()?;
+    let name = rest.components().next()?;
+    Some(Path::new("/nix/store").join(name))
+}
+
+/// Installed package together with its store paths in the current generation
+#[derive(Debug, Clone)]
+pub struct InstalledPackage {
+    pub attr: String,
+    pub version: Option<String>,
+    pub paths: Vec<PathBuf>,
+}
+
+/// Map the packages of `scope` to their store paths in the current generation.
+/// Declared packages that have not been built yet have no paths.
+pub fn installed(scope: Scope, md: &Metadata) -> Result<Vec<InstalledPackage>> {
+    let pkgs = scope::list(scope, md)?;
+    if scope == Scope::Profile {
+        let paths = crate::list::profile_store_paths();
+        return Ok(pkgs
+            .into_iter()
+            .map(|pkg| {
+                let attr = pkg.attr.to_string();
+                InstalledPackage {
+                    paths: paths
+                        .get(&attr_name(&attr))
+                        .map(PathBuf::from)
+                        .into_iter()
+                        .collect(),
+                    attr,
+                    version: pkg.version,
+                }
+            })
+            .collect());
+    }
+
+    crate::host::require_local(scope == Scope::Home)?;
+    let root =
+        generation_root(scope).ok_or_else(|| anyhow!("no current {} generation found", scope))?;
+    let built = references(&root)?;
+    let attrs = pkgs.iter().map(|x| x.attr.to_string()).collect::<Vec<_>>();
+    let names: Vec<Option<String>> = serde_json::from_str(&flake::eval_configuration(
+        scope == Scope::Home,
+        &format!("c: {}", flake::attr_names_expr(&attrs)),
+    )?)?;
+    Ok(pkgs
+        .into_iter()
+        .zip(names)
+        .map(|(pkg, name)| InstalledPackage {
+            attr: pkg.attr.to_string(),
+            version: pkg.version,
+            paths: built
+                .iter()
+                .filter(|x| name.as_deref() == Some(store_name(x).as_str()))
+                .cloned()
+                .collect(),
+        })
+        .collect())
+}
+
+/// Build or substitute `installable` and return its output paths
+pub fn build(installable: &str) -> Result<Vec<PathBuf>> {
+    let output = Command::new("nix")
+        .args(["build", "--no-link", "--print-out-paths", installable])
+        .stderr(Stdio::inherit())
+        .output()
+        .context("failed to run nix build")?;
+    if !output.status.success() {
+        return Err(anyhow!("failed to build {}", installable));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Closure of `paths`, including the paths themselves
+pub fn requisites(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    if paths.is_empty() {
+        return Ok(vec![]);
+    }
+    let output = Command::new("nix-store")
+        .args(["--query", "--requisites"])
+        .args(paths)
+        .output()
+        .context("failed to run nix-store")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "failed to query closure: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// NAR size of every path in the closure of `root`
+pub fn nar_sizes(root: &Path) -> Result<HashMap<PathBuf, u64>> {
+    let output = Command::new("nix")
+        .args(["path-info", "--json", "--recursive"])
+        .arg(root)
+        .output()
+        .context("failed to run nix path-info")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "failed to query path info of {}: {}",
+            root.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    // Newer Nix versions return an object keyed by path, older ones a list
+    let entries: Vec<(String, &serde_json::Value)> = match &info {
+        serde_json::Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+        serde_json::Value::Array(list) => list
+            .iter()
+            .filter_map(|v| Some((v["path"].as_str()?.to_string(), v)))
+            .collect(),
+        _ => vec![],
+    };
+    Ok(entries
+        .into_iter()
+        .filter_map(|(path, v)| Some((PathBuf::from(path), v["narSize"].as_u64()?)))
+        .collect())
+}
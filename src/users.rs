@@ -0,0 +1,65 @@
+use crate::{
+    config, edit, files, host,
+    scope::Scope,
+    search::{self, lookup},
+};
+use anyhow::{Result, anyhow};
+use libsnow::{Package, PackageAttr, metadata::Metadata};
+use std::path::PathBuf;
+
+/// User whose packages to act on, the current user if none is given
+pub fn name(user: Option<String>) -> String {
+    user.unwrap_or_else(|| std::env::var("USER").unwrap_or_default())
+}
+
+/// `users.users.<name>.packages`
+fn option(name: &str) -> String {
+    format!("{}.packages", edit::attr("users.users", name))
+}
+
+/// File declaring the user `name`: the first file of the NixOS flake mentioning
+/// `users.users.<name>`, or the NixOS package file if there is none
+pub fn package_file(name: &str) -> Result<PathBuf> {
+    let base = match host::other(false) {
+        Some(host) => host::package_file(&host, false)?,
+        None => config::system_config_file()
+            .ok_or_else(|| anyhow!("NixOS configuration file is not set, run `snow init`"))?,
+    };
+    Ok(edit::declaring_file(&base, "users.users", name).unwrap_or(base))
+}
+
+fn file(name: &str, file: Option<&str>) -> Result<PathBuf> {
+    match file {
+        Some(file) => files::resolve(file, Scope::System),
+        None => package_file(name),
+    }
+}
+
+/// Add `packages` to the packages of the user `name` and rebuild the system
+pub async fn install(
+    name: &str,
+    file: Option<&str>,
+    packages: &[&str],
+    md: &Metadata,
+) -> Result<()> {
+    search::validate(md, packages)?;
+    edit::add_packages(&self::file(name, file)?, &option(name), packages)?;
+    files::rebuild(Scope::System).await
+}
+
+/// Remove `packages` from the packages of the user `name` and rebuild the system
+pub async fn remove(name: &str, file: Option<&str>, packages: &[&str]) -> Result<()> {
+    edit::remove_packages(&self::file(name, file)?, &option(name), packages)?;
+    files::rebuild(Scope::System).await
+}
+
+/// Packages declared for the user `name`
+pub fn list(name: &str, md: &Metadata) -> Result<Vec<Package>> {
+    Ok(edit::list_packages(&package_file(name)?, &option(name))?
+        .into_iter()
+        .map(|attr| Package {
+            version: lookup(md, &attr).and_then(|x| x.version),
+            attr: PackageAttr::NixPkgs { attr },
+        })
+        .collect())
+}
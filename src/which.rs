@@ -0,0 +1,58 @@
+use crate::{PKGSTYLE, VERSIONSTYLE, binaries::find_in_path, scope::Scope, search::lookup, store};
+use anyhow::{Result, anyhow};
+use libsnow::metadata::Metadata;
+use owo_colors::{OwoColorize, Stream::Stdout};
+
+/// Trace `command` on PATH back to its store path, package and scope
+pub async fn which(command: &str) -> Result<()> {
+    let found =
+        find_in_path(command).ok_or_else(|| anyhow!("{} was not found in PATH", command))?;
+    let resolved = found.canonicalize()?;
+    if found == resolved {
+        println!("{}", found.display());
+    } else {
+        println!("{} -> {}", found.display(), resolved.display());
+    }
+
+    let Some(path) = store::store_path(&resolved) else {
+        println!("{} is not managed by Nix", command);
+        return Ok(());
+    };
+    let (pname, version) = store::split_name(&store::store_name(&path));
+
+    let md = Metadata::connect().await?;
+    let scope = Scope::ALL
+        .into_iter()
+        .filter(Scope::is_configured)
+        .find(|s| {
+            store::generation_root(*s)
+                .and_then(|root| store::references(&root).ok())
+                .is_some_and(|refs| refs.contains(&path))
+        });
+    let attr = scope
+        .and_then(|s| store::installed(s, &md).ok())
+        .and_then(|pkgs| pkgs.into_iter().find(|x| x.paths.contains(&path)))
+        .map(|x| x.attr)
+        .or_else(|| lookup(&md, &pname).map(|x| x.attribute))
+        .unwrap_or(pname);
+
+    let pkg = match version {
+        Some(v) => format!(
+            "{} ({})",
+            attr.if_supports_color(Stdout, |t| t.style(*PKGSTYLE)),
+            v.if_supports_color(Stdout, |t| t.style(*VERSIONSTYLE))
+        ),
+        None => attr
+            .if_supports_color(Stdout, |t| t.style(*PKGSTYLE))
+            .to_string(),
+    };
+    match scope {
+        Some(s) => println!("{} from the {} scope", pkg, s),
+        None => println!(
+            "{} from {}, not installed in any scope",
+            pkg,
+            path.display()
+        ),
+    }
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use crate::{
+    PKGSTYLE, WARNINGSTYLE, flake,
+    scope::{self, Scope, attr_name},
+    store,
+};
+use anyhow::{Context, Result, anyhow};
+use libsnow::metadata::Metadata;
+use owo_colors::{OwoColorize, Stream::Stdout};
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Lines of `file` mentioning `attr` as a whole identifier
+fn find_lines(file: &Path, attr: &str) -> Vec<(usize, String)> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || "_-'.".contains(c);
+    std::fs::read_to_string(file)
+        .unwrap_or_default()
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+            code.match_indices(attr).any(|(i, _)| {
+                let before = code[..i].chars().next_back();
+                let after = code[i + attr.len()..].chars().next();
+                // `pkgs.hello` is a reference to hello, `foo.hello` or `hello-wrapped` are not
+                let before_ok = match before {
+                    None => true,
+                    Some(_) if code[..i].ends_with("pkgs.") => true,
+                    Some(c) => !is_ident(c),
+                };
+                before_ok && !after.is_some_and(is_ident)
+            })
+        })
+        .map(|(i, line)| (i + 1, line.trim().to_string()))
+        .collect()
+}
+
+/// Files that declare packages of `scope`: the configured file and the rest of its flake
+fn declaration_files(scope: Scope) -> Vec<PathBuf> {
+    let file = match scope {
+        Scope::System => crate::config::system_config_file(),
+        Scope::Home => crate::config::home_config_file(),
+        Scope::Profile => None,
+    };
+    let Some(file) = file else {
+        return vec![];
+    };
+    let mut files = vec![];
+    match flake::flake_dir(&file) {
+        Some(dir) => flake::nix_files(&dir, &mut files),
+        None => files.push(file),
+    }
+    files.sort();
+    files
+}
+
+/// Store paths in the closure of `root` whose name is the package name of `attr`
+fn find_in_closure(closure: &[PathBuf], attr: &str) -> Vec<PathBuf> {
+    let name = attr.rsplit('.').next().unwrap_or(attr);
+    closure
+        .iter()
+        .filter(|x| store::split_name(&store::store_name(x)).0 == name)
+        .cloned()
+        .collect()
+}
+
+/// Explain why `attr`, or `target_path` if given, is present in `scope`. Returns whether it is.
+fn explain(scope: Scope, attr: &str, target_path: Option<&PathBuf>, md: &Metadata) -> Result<bool> {
+    let mut explained = false;
+    let installed = scope::list(scope, md)?;
+    if let Some(pkg) = installed
+        .iter()
+        .find(|x| attr_name(&x.attr.to_string()) == attr)
+    {
+        explained = true;
+        println!(
+            "{} is declared directly in the {} scope",
+            pkg.attr
+                .to_string()
+                .if_supports_color(Stdout, |t| t.style(*PKGSTYLE)),
+            scope
+        );
+        for file in declaration_files(scope) {
+            for (line, text) in find_lines(&file, attr) {
+                println!("  {}:{}: {}", file.display(), line, text);
+            }
+        }
+    }
+
+    let Some(root) = store::generation_root(scope) else {
+        return Ok(explained);
+    };
+    let closure = store::requisites(std::slice::from_ref(&root))?;
+    let paths = match target_path {
+        Some(path) => closure.iter().filter(|x| *x == path).cloned().collect(),
+        None => find_in_closure(&closure, attr),
+    };
+    if paths.is_empty() {
+        return Ok(explained);
+    }
+
+    let packages = store::installed(scope, md)?;
+    for path in paths {
+        let dependents = packages
+            .iter()
+            .filter(|x| !x.paths.contains(&path))
+            .filter(|x| {
+                store::requisites(&x.paths)
+                    .map(|c| c.contains(&path))
+                    .unwrap_or(false)
+            })
+            .map(|x| x.attr.clone())
+            .collect::<Vec<_>>();
+        if dependents.is_empty() {
+            continue;
+        }
+        explained = true;
+        println!(
+            "{} is a dependency in the {} scope, pulled in by: {}",
+            path.display(),
+            scope,
+            dependents.join(", ")
+        );
+        let status = Command::new("nix")
+            .args(["why-depends", "--all"])
+            .arg(&root)
+            .arg(&path)
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .context("failed to run nix why-depends")?;
+        if !status.success() {
+            return Err(anyhow!("nix why-depends failed"));
+        }
+    }
+    Ok(explained)
+}
+
+/// Explain why `target`, an attribute or store path, is present
+pub async fn why(target: &str) -> Result<()> {
+    let md = Metadata::connect().await?;
+    let target_path = if target.starts_with("/nix/store/") {
+        Some(
+            store::store_path(Path::new(target))
+                .ok_or_else(|| anyhow!("{} is not a store path", target))?,
+        )
+    } else {
+        None
+    };
+    let attr = match &target_path {
+        Some(path) => store::split_name(&store::store_name(path)).0,
+        None => target.to_string(),
+    };
+    let mut explained = false;
+
+    // A scope that cannot be inspected should not hide the answer from the other ones
+    for scope in Scope::ALL.into_iter().filter(Scope::is_configured) {
+        match explain(scope, &attr, target_path.as_ref(), &md) {
+            Ok(x) => explained |= x,
+            Err(e) => eprintln!(
+                "{} failed to check the {} scope: {}",
+                "warning:".if_supports_color(Stdout, |t| t.style(*WARNINGSTYLE)),
+                scope,
+                e
+            ),
+        }
+    }
+
+    if !explained {
+        return Err(anyhow!("{} is not present in any scope", target));
+    }
+    Ok(())
+}